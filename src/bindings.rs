@@ -0,0 +1,906 @@
+use crate::config::Config;
+use crate::cycle_state::SwitchError;
+use crate::protocol::Command;
+use anyhow::{Context, Result};
+use evdev::{Key, RelativeAxisType};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Modifiers held for a chord. Left and right keys count the same.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub meta: bool,
+    /// Matches whatever modifiers are held, for the mouse buttons
+    pub any: bool,
+}
+
+impl Modifiers {
+    pub const ANY: Modifiers = Modifiers {
+        ctrl: false,
+        shift: false,
+        alt: false,
+        meta: false,
+        any: true,
+    };
+
+    /// Add the modifier `key` is, returning false if it isn't one
+    fn add(&mut self, key: Key) -> bool {
+        let flag = match key {
+            Key::KEY_LEFTCTRL | Key::KEY_RIGHTCTRL => &mut self.ctrl,
+            Key::KEY_LEFTSHIFT | Key::KEY_RIGHTSHIFT => &mut self.shift,
+            Key::KEY_LEFTALT | Key::KEY_RIGHTALT => &mut self.alt,
+            Key::KEY_LEFTMETA | Key::KEY_RIGHTMETA => &mut self.meta,
+            _ => return false,
+        };
+        *flag = true;
+        true
+    }
+}
+
+pub fn is_modifier(key: Key) -> bool {
+    Modifiers::default().add(key)
+}
+
+/// A wheel notch: `REL_WHEEL` scrolls up and down, `REL_HWHEEL` tilts left and right
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Wheel {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Wheel {
+    /// Which way `value` notches on `axis` go, if it's a wheel
+    pub fn from_axis(axis: RelativeAxisType, value: i32) -> Option<Self> {
+        match axis {
+            RelativeAxisType::REL_WHEEL if value > 0 => Some(Wheel::Up),
+            RelativeAxisType::REL_WHEEL if value < 0 => Some(Wheel::Down),
+            RelativeAxisType::REL_HWHEEL if value > 0 => Some(Wheel::Right),
+            RelativeAxisType::REL_HWHEEL if value < 0 => Some(Wheel::Left),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Wheel::Up => "WheelUp",
+            Wheel::Down => "WheelDown",
+            Wheel::Left => "WheelLeft",
+            Wheel::Right => "WheelRight",
+        }
+    }
+}
+
+/// What sets a chord off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Trigger {
+    Key(Key),
+    Wheel(Wheel),
+}
+
+/// A key, button or wheel notch while exactly `modifiers` are held, e.g. `Ctrl+F1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub modifiers: Modifiers,
+    /// A key or button that must be held too, for wheel chords like `BTN_SIDE+WheelUp`
+    pub held: Option<Key>,
+    pub trigger: Trigger,
+}
+
+impl Chord {
+    /// A key or button pressed on its own
+    pub fn key(key: Key) -> Self {
+        Chord {
+            modifiers: Modifiers::default(),
+            held: None,
+            trigger: Trigger::Key(key),
+        }
+    }
+
+    /// A key or button that fires whatever modifiers are held
+    pub fn any_modifiers(key: Key) -> Self {
+        Chord {
+            modifiers: Modifiers::ANY,
+            ..Chord::key(key)
+        }
+    }
+
+    /// The keys and buttons this chord needs, besides modifiers
+    pub fn keys(&self) -> impl Iterator<Item = Key> {
+        let trigger = match self.trigger {
+            Trigger::Key(key) => Some(key),
+            Trigger::Wheel(_) => None,
+        };
+        self.held.into_iter().chain(trigger)
+    }
+}
+
+/// Read a key or button: an evdev name (`KEY_F1`, `BTN_SIDE`), a key name
+/// without `KEY_` (`F1`, `Grave`, `1`), or a numeric code (`276`) where no
+/// name matches
+fn parse_key(name: &str) -> Option<Key> {
+    let upper = name.to_uppercase();
+    let named = if upper.starts_with("KEY_") || upper.starts_with("BTN_") {
+        Key::from_str(&upper).ok()
+    } else {
+        Key::from_str(&format!("KEY_{}", upper)).ok()
+    };
+    named.or_else(|| name.parse::<u16>().ok().map(Key::new))
+}
+
+fn key_name(key: Key) -> String {
+    let name = format!("{:?}", key);
+    name.strip_prefix("KEY_").unwrap_or(&name).to_string()
+}
+
+impl FromStr for Chord {
+    type Err = anyhow::Error;
+
+    /// Modifiers and key are joined with `+` and matched case-insensitively.
+    /// Wheel chords may also hold one key or button: `BTN_SIDE+WheelUp`.
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let (trigger_name, gate_names) = parts.split_last().unwrap();
+
+        let trigger = match trigger_name.to_lowercase().as_str() {
+            "wheelup" => Trigger::Wheel(Wheel::Up),
+            "wheeldown" => Trigger::Wheel(Wheel::Down),
+            "wheelleft" => Trigger::Wheel(Wheel::Left),
+            "wheelright" => Trigger::Wheel(Wheel::Right),
+            _ => {
+                let key = parse_key(trigger_name)
+                    .with_context(|| format!("Unknown key '{}' in '{}'", trigger_name, s))?;
+                if is_modifier(key) {
+                    anyhow::bail!("'{}' needs a key besides the modifiers", s);
+                }
+                Trigger::Key(key)
+            }
+        };
+        let is_wheel = matches!(trigger, Trigger::Wheel(_));
+
+        let mut modifiers = Modifiers::default();
+        let mut held = None;
+        for name in gate_names {
+            let flag = match name.to_lowercase().as_str() {
+                "ctrl" | "control" => &mut modifiers.ctrl,
+                "shift" => &mut modifiers.shift,
+                "alt" => &mut modifiers.alt,
+                "super" | "meta" | "win" | "logo" => &mut modifiers.meta,
+                _ if is_wheel => {
+                    let key = parse_key(name).with_context(|| {
+                        format!("Unknown modifier or key '{}' in '{}'", name, s)
+                    })?;
+                    if held.replace(key).is_some() {
+                        anyhow::bail!("'{}' can only hold one key besides modifiers", s);
+                    }
+                    continue;
+                }
+                _ => anyhow::bail!("Unknown modifier '{}' in '{}'", name, s),
+            };
+            *flag = true;
+        }
+
+        let scrolls = matches!(trigger, Trigger::Wheel(Wheel::Up | Wheel::Down));
+        if scrolls && modifiers == Modifiers::default() && held.is_none() {
+            anyhow::bail!(
+                "'{}' needs a modifier or a held button, so scrolling still works",
+                s
+            );
+        }
+
+        Ok(Chord {
+            modifiers,
+            held,
+            trigger,
+        })
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [
+            (self.modifiers.ctrl, "Ctrl"),
+            (self.modifiers.shift, "Shift"),
+            (self.modifiers.alt, "Alt"),
+            (self.modifiers.meta, "Super"),
+        ] {
+            if held {
+                write!(f, "{}+", name)?;
+            }
+        }
+        if let Some(key) = self.held {
+            write!(f, "{}+", key_name(key))?;
+        }
+        match self.trigger {
+            Trigger::Key(key) => f.write_str(&key_name(key)),
+            Trigger::Wheel(wheel) => f.write_str(wheel.name()),
+        }
+    }
+}
+
+/// Read one `[bindings]` entry, e.g. `"Ctrl+F1" = "switch:1"`
+fn parse_binding(chord: &str, command: &str) -> Result<(Chord, Command)> {
+    let chord: Chord = chord.parse()?;
+    let command = Command::from_str(command)
+        .with_context(|| format!("Unknown command '{}' for {}", command, chord))?;
+    if matches!(
+        command,
+        Command::Status | Command::List | Command::Subscribe | Command::Quit
+    ) {
+        anyhow::bail!("'{:?}' can't be bound to {}", command, chord);
+    }
+    Ok((chord, command))
+}
+
+/// Which window must have focus for a binding to fire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusRule {
+    /// Whatever has focus
+    Always,
+    /// An EVE client or the overlay, so the buttons still go back and
+    /// forward in a browser
+    #[default]
+    EveFocused,
+    /// Anything but an EVE client or the overlay
+    EveUnfocused,
+}
+
+impl FocusRule {
+    pub fn allows(self, eve_focused: bool) -> bool {
+        match self {
+            FocusRule::Always => true,
+            FocusRule::EveFocused => eve_focused,
+            FocusRule::EveUnfocused => !eve_focused,
+        }
+    }
+}
+
+/// A bound command and when it applies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub command: Command,
+    pub focus: FocusRule,
+}
+
+impl Binding {
+    fn always(command: Command) -> Self {
+        Self {
+            command,
+            focus: FocusRule::Always,
+        }
+    }
+}
+
+/// Every chord the daemon acts on: the mouse buttons, which fire under any
+/// modifiers, then `[bindings]`, which wins where both bind the same button.
+/// Bad entries are skipped.
+pub fn from_config(
+    config: &Config,
+    groups: &BTreeMap<String, Vec<String>>,
+) -> HashMap<Chord, Binding> {
+    let mut bindings = HashMap::new();
+
+    if config.enable_mouse_buttons {
+        let group = config.mouse_group.clone().filter(|name| {
+            let found = groups.contains_key(name);
+            if !found {
+                eprintln!(
+                    "Warning: {}, mouse buttons cycle all clients",
+                    SwitchError::GroupNotFound(name.clone())
+                );
+            }
+            found
+        });
+
+        // Modifiers are often held in EVE, for module and overheat keys, so
+        // the buttons don't care about them
+        bindings.insert(
+            Chord::any_modifiers(Key::new(config.forward_button)),
+            Binding {
+                command: Command::Forward {
+                    group: group.clone(),
+                },
+                focus: config.forward_button_focus,
+            },
+        );
+        bindings.insert(
+            Chord::any_modifiers(Key::new(config.backward_button)),
+            Binding {
+                command: Command::Backward { group },
+                focus: config.backward_button_focus,
+            },
+        );
+    }
+
+    for (chord, command) in &config.bindings {
+        match parse_binding(chord, command) {
+            Ok((chord, command)) => {
+                if let Trigger::Key(key) = chord.trigger {
+                    if chord == Chord::key(key) {
+                        bindings.remove(&Chord::any_modifiers(key));
+                    }
+                }
+                bindings.insert(chord, Binding::always(command));
+            }
+            Err(e) => eprintln!("Warning: Ignoring binding '{}': {:#}", chord, e),
+        }
+    }
+
+    bindings
+}
+
+/// What the device readers hand the engine
+pub enum Input {
+    Key {
+        device: usize,
+        key: Key,
+        value: i32,
+    },
+    Wheel {
+        wheel: Wheel,
+        notches: u32,
+    },
+    Gone(usize),
+    /// The config was reloaded: match these from now on
+    Rebind {
+        bindings: HashMap<Chord, Binding>,
+        wheel: WheelSettings,
+    },
+}
+
+/// How wheel notches turn into steps, from the `wheel_*` config keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WheelSettings {
+    /// Notches one way before a binding fires
+    pub notches: u32,
+    /// Least time between two firings; notches in between are dropped
+    pub interval: Duration,
+}
+
+/// Matches chords pressed on any device against the bindings. Modifiers
+/// count wherever they're held, so Ctrl on the keyboard and a mouse button
+/// still make `Ctrl+BTN_SIDE`.
+pub struct BindingEngine {
+    bindings: HashMap<Chord, Binding>,
+    wheel: WheelSettings,
+    /// (device, key) of everything currently down
+    held: HashSet<(usize, Key)>,
+    /// Notches scrolled towards the next wheel step, and which way
+    scrolled: Option<(Wheel, u32)>,
+    last_scroll: Option<Instant>,
+    /// Chords of wheel gates pressed but not scrolled with yet, which fire
+    /// on release
+    deferred: HashMap<(usize, Key), Chord>,
+}
+
+impl BindingEngine {
+    pub fn new(bindings: HashMap<Chord, Binding>, wheel: WheelSettings) -> Self {
+        Self {
+            bindings,
+            wheel,
+            held: HashSet::new(),
+            scrolled: None,
+            last_scroll: None,
+            deferred: HashMap::new(),
+        }
+    }
+
+    fn modifiers(&self) -> Modifiers {
+        let mut modifiers = Modifiers::default();
+        for (_, key) in &self.held {
+            modifiers.add(*key);
+        }
+        modifiers
+    }
+
+    /// Feed one input that arrived at `now`, returning the binding it completes.
+    /// Autorepeat (value 2) completes nothing, and releases only complete the
+    /// press of a wheel gate that wasn't scrolled with.
+    fn handle(&mut self, input: Input, now: Instant) -> Option<(Chord, &Binding)> {
+        let (device, key, value) = match input {
+            Input::Key { device, key, value } => (device, key, value),
+            Input::Wheel { wheel, notches } => return self.scroll(wheel, notches, now),
+            Input::Gone(device) => {
+                // It can't send the releases anymore
+                self.held.retain(|(held_on, _)| *held_on != device);
+                self.deferred.retain(|(held_on, _), _| *held_on != device);
+                return None;
+            }
+            Input::Rebind { bindings, wheel } => {
+                self.bindings = bindings;
+                self.wheel = wheel;
+                self.scrolled = None;
+                self.deferred.clear();
+                return None;
+            }
+        };
+
+        match value {
+            0 => {
+                self.held.remove(&(device, key));
+            }
+            1 => {
+                self.held.insert((device, key));
+            }
+            _ => return None,
+        }
+        // Notches count towards one chord only
+        self.scrolled = None;
+
+        let chord = if value == 0 {
+            self.deferred.remove(&(device, key))?
+        } else {
+            if is_modifier(key) {
+                return None;
+            }
+            let exact = Chord {
+                modifiers: self.modifiers(),
+                ..Chord::key(key)
+            };
+            let chord = [exact, Chord::any_modifiers(key)]
+                .into_iter()
+                .find(|chord| self.bindings.contains_key(chord))?;
+
+            // Holding a wheel gate to scroll shouldn't also act on the press
+            if self.bindings.keys().any(|gated| gated.held == Some(key)) {
+                self.deferred.insert((device, key), chord);
+                return None;
+            }
+            chord
+        };
+        self.bindings.get(&chord).map(|binding| (chord, binding))
+    }
+
+    fn scroll(&mut self, wheel: Wheel, notches: u32, now: Instant) -> Option<(Chord, &Binding)> {
+        let modifiers = self.modifiers();
+        let mut held: Vec<Key> = self
+            .held
+            .iter()
+            .map(|(_, key)| *key)
+            .filter(|key| !is_modifier(*key))
+            .collect();
+        held.sort();
+
+        // Chords holding a key or button come before plain ones
+        let chord = held
+            .into_iter()
+            .map(Some)
+            .chain([None])
+            .map(|held| Chord {
+                modifiers,
+                held,
+                trigger: Trigger::Wheel(wheel),
+            })
+            .find(|chord| self.bindings.contains_key(chord))?;
+        if let Some(gate) = chord.held {
+            self.deferred.retain(|(_, key), _| *key != gate);
+        }
+
+        let scrolled = match self.scrolled {
+            Some((direction, count)) if direction == wheel => count + notches,
+            _ => notches,
+        };
+        if scrolled < self.wheel.notches {
+            self.scrolled = Some((wheel, scrolled));
+            return None;
+        }
+        self.scrolled = None;
+
+        if self
+            .last_scroll
+            .is_some_and(|last| now.duration_since(last) < self.wheel.interval)
+        {
+            return None;
+        }
+        self.last_scroll = Some(now);
+
+        self.bindings.get(&chord).map(|binding| (chord, binding))
+    }
+
+    /// Match `inputs` on a background thread, handing each bound command to
+    /// `dispatch`, until every reader is gone or `running` is cleared
+    pub fn spawn(
+        mut self,
+        inputs: Receiver<Input>,
+        running: Arc<AtomicBool>,
+        dispatch: impl Fn(Binding) + Send + 'static,
+    ) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || {
+            for input in inputs {
+                if !running.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Some((_, binding)) = self.handle(input, Instant::now()) {
+                    dispatch(binding.clone());
+                }
+            }
+            println!("Input bindings stopped");
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+
+    fn press(device: usize, key: Key) -> Input {
+        Input::Key {
+            device,
+            key,
+            value: 1,
+        }
+    }
+
+    fn release(device: usize, key: Key) -> Input {
+        Input::Key {
+            device,
+            key,
+            value: 0,
+        }
+    }
+
+    fn engine(bindings: &[(&str, &str)]) -> BindingEngine {
+        BindingEngine::new(
+            bindings
+                .iter()
+                .map(|(chord, command)| {
+                    let (chord, command) = parse_binding(chord, command).unwrap();
+                    (chord, Binding::always(command))
+                })
+                .collect(),
+            WheelSettings {
+                notches: 2,
+                interval: Duration::from_millis(100),
+            },
+        )
+    }
+
+    fn fired_at(engine: &mut BindingEngine, input: Input, now: Instant) -> Option<Command> {
+        engine
+            .handle(input, now)
+            .map(|(_, binding)| binding.command.clone())
+    }
+
+    fn fired(engine: &mut BindingEngine, input: Input) -> Option<Command> {
+        fired_at(engine, input, Instant::now())
+    }
+
+    #[test]
+    fn test_parse_chord() {
+        let chord: Chord = "Ctrl+F1".parse().unwrap();
+        assert_eq!(chord.trigger, Trigger::Key(Key::KEY_F1));
+        assert_eq!(
+            chord.modifiers,
+            Modifiers {
+                ctrl: true,
+                ..Modifiers::default()
+            }
+        );
+
+        let chord: Chord = "alt + grave".parse().unwrap();
+        assert_eq!(chord.trigger, Trigger::Key(Key::KEY_GRAVE));
+        assert!(chord.modifiers.alt);
+        assert_eq!(chord.to_string(), "Alt+GRAVE");
+
+        assert_eq!(
+            "KEY_KP1".parse::<Chord>().unwrap(),
+            Chord::key(Key::KEY_KP1)
+        );
+
+        assert!("Hyper+F1".parse::<Chord>().is_err());
+        assert!("Ctrl+Nope".parse::<Chord>().is_err());
+        assert!("Ctrl+Shift".parse::<Chord>().is_err());
+        assert!("".parse::<Chord>().is_err());
+        // Only wheel chords hold a key besides modifiers
+        assert!("BTN_SIDE+F1".parse::<Chord>().is_err());
+    }
+
+    #[test]
+    fn test_parse_wheel_chords() {
+        let chord: Chord = "btn_side+wheelup".parse().unwrap();
+        assert_eq!(chord.held, Some(Key::BTN_SIDE));
+        assert_eq!(chord.trigger, Trigger::Wheel(Wheel::Up));
+        assert_eq!(chord.to_string(), "BTN_SIDE+WheelUp");
+        assert_eq!(chord.keys().collect::<Vec<_>>(), vec![Key::BTN_SIDE]);
+
+        assert_eq!(
+            "Ctrl+WheelDown".parse::<Chord>().unwrap().to_string(),
+            "Ctrl+WheelDown"
+        );
+        // Tilting needs no gate, scrolling does
+        assert!("WheelLeft".parse::<Chord>().is_ok());
+        assert!("WheelUp".parse::<Chord>().is_err());
+        assert!("BTN_SIDE+BTN_EXTRA+WheelUp".parse::<Chord>().is_err());
+    }
+
+    #[test]
+    fn test_parse_buttons() {
+        assert_eq!(parse_key("BTN_SIDE"), Some(Key::BTN_SIDE));
+        assert_eq!(parse_key("btn_extra"), Some(Key::BTN_EXTRA));
+        assert_eq!(parse_key("275"), Some(Key::BTN_SIDE));
+
+        // Digit keys are names, not codes
+        let chord: Chord = "Alt+1".parse().unwrap();
+        assert_eq!(chord.trigger, Trigger::Key(Key::KEY_1));
+
+        let chord: Chord = "Shift+BTN_SIDE".parse().unwrap();
+        assert_eq!(chord.to_string(), "Shift+BTN_SIDE");
+    }
+
+    #[test]
+    fn test_parse_binding() {
+        let (_, command) = parse_binding("Ctrl+F1", "switch:1").unwrap();
+        assert_eq!(
+            command,
+            Command::Switch {
+                target: 1,
+                group: None
+            }
+        );
+
+        let (_, command) = parse_binding("BTN_0", "focus:Alpha").unwrap();
+        assert_eq!(
+            command,
+            Command::Focus {
+                character: "Alpha".to_string()
+            }
+        );
+
+        assert!(parse_binding("Ctrl+F1", "jump").is_err());
+        assert!(parse_binding("Ctrl+F1", "quit").is_err());
+    }
+
+    #[test]
+    fn test_bindings_override_mouse_buttons() {
+        // `[hotkeys]` is the older name of the table
+        let config = test_config(
+            r#"
+            mouse_group = "dps"
+            backward_button_focus = "always"
+
+            [hotkeys]
+            "276" = "last"
+            "Ctrl+F1" = "switch:1"
+            "Ctrl+Nope" = "forward"
+            "#,
+        );
+
+        let groups = BTreeMap::from([("dps".to_string(), vec!["Alpha".to_string()])]);
+        let bindings = from_config(&config, &groups);
+
+        let forward = Chord::key(Key::new(config.forward_button));
+        let backward = Chord::any_modifiers(Key::new(config.backward_button));
+
+        assert_eq!(bindings.len(), 3);
+        assert_eq!(bindings[&forward], Binding::always(Command::Last));
+        assert_eq!(
+            bindings[&backward],
+            Binding {
+                command: Command::Backward {
+                    group: Some("dps".to_string())
+                },
+                focus: FocusRule::Always,
+            }
+        );
+
+        // A missing group falls back to cycling everything
+        let bindings = from_config(&config, &BTreeMap::new());
+        assert_eq!(
+            bindings[&backward].command,
+            Command::Backward { group: None }
+        );
+        assert_eq!(config.forward_button_focus, FocusRule::EveFocused);
+    }
+
+    #[test]
+    fn test_focus_rules() {
+        let rule: FocusRule = serde_json::from_str("\"eve_unfocused\"").unwrap();
+        assert_eq!(rule, FocusRule::EveUnfocused);
+        assert!(rule.allows(false));
+        assert!(!rule.allows(true));
+
+        assert!(FocusRule::EveFocused.allows(true));
+        assert!(!FocusRule::EveFocused.allows(false));
+        assert!(FocusRule::Always.allows(false));
+    }
+
+    #[test]
+    fn test_rebind_replaces_bindings() {
+        let mut engine = engine(&[("Ctrl+F1", "switch:1")]);
+        fired(&mut engine, press(0, Key::KEY_LEFTCTRL));
+
+        let (chord, command) = parse_binding("Ctrl+F2", "last").unwrap();
+        let rebind = Input::Rebind {
+            bindings: HashMap::from([(chord, Binding::always(command))]),
+            wheel: engine.wheel,
+        };
+        assert_eq!(fired(&mut engine, rebind), None);
+
+        // Keys held across the reload still count
+        assert_eq!(fired(&mut engine, press(0, Key::KEY_F1)), None);
+        assert_eq!(
+            fired(&mut engine, press(0, Key::KEY_F2)),
+            Some(Command::Last)
+        );
+    }
+
+    #[test]
+    fn test_modifiers_span_devices() {
+        let mut engine = engine(&[("Ctrl+BTN_SIDE", "last"), ("BTN_SIDE", "forward")]);
+
+        // Ctrl on the keyboard, the button on the mouse
+        assert_eq!(fired(&mut engine, press(0, Key::KEY_RIGHTCTRL)), None);
+        assert_eq!(
+            fired(&mut engine, press(1, Key::BTN_SIDE)),
+            Some(Command::Last)
+        );
+
+        // Autorepeat and releases don't fire again
+        let repeat = Input::Key {
+            device: 1,
+            key: Key::BTN_SIDE,
+            value: 2,
+        };
+        assert_eq!(fired(&mut engine, repeat), None);
+        assert_eq!(fired(&mut engine, release(1, Key::BTN_SIDE)), None);
+
+        // Unplugging the keyboard releases its Ctrl
+        assert_eq!(fired(&mut engine, Input::Gone(0)), None);
+        assert_eq!(
+            fired(&mut engine, press(1, Key::BTN_SIDE)),
+            Some(Command::Forward { group: None })
+        );
+    }
+
+    #[test]
+    fn test_chords_need_exact_modifiers() {
+        let mut engine = engine(&[("Ctrl+F1", "switch:1"), ("Ctrl+Shift+F1", "switch:2")]);
+        fired(&mut engine, press(0, Key::KEY_LEFTCTRL));
+        fired(&mut engine, press(0, Key::KEY_LEFTSHIFT));
+
+        assert_eq!(
+            fired(&mut engine, press(0, Key::KEY_F1)),
+            Some(Command::Switch {
+                target: 2,
+                group: None
+            })
+        );
+
+        // Both Ctrl keys held, one released: still held
+        fired(&mut engine, release(0, Key::KEY_LEFTSHIFT));
+        fired(&mut engine, press(0, Key::KEY_RIGHTCTRL));
+        fired(&mut engine, release(0, Key::KEY_LEFTCTRL));
+        assert_eq!(
+            fired(&mut engine, press(0, Key::KEY_F1)),
+            Some(Command::Switch {
+                target: 1,
+                group: None
+            })
+        );
+
+        // Unbound chords do nothing
+        fired(&mut engine, release(0, Key::KEY_RIGHTCTRL));
+        assert_eq!(fired(&mut engine, press(0, Key::KEY_F1)), None);
+    }
+
+    #[test]
+    fn test_mouse_buttons_ignore_modifiers() {
+        let config = test_config(
+            r#"
+            [bindings]
+            "Shift+BTN_SIDE" = "stack"
+            "#,
+        );
+        let mut engine = BindingEngine::new(
+            from_config(&config, &BTreeMap::new()),
+            config.wheel_settings(),
+        );
+
+        // BTN_SIDE is the default backward button
+        fired(&mut engine, press(0, Key::KEY_LEFTCTRL));
+        assert_eq!(
+            fired(&mut engine, press(1, Key::BTN_SIDE)),
+            Some(Command::Backward { group: None })
+        );
+        fired(&mut engine, release(1, Key::BTN_SIDE));
+        fired(&mut engine, release(0, Key::KEY_LEFTCTRL));
+
+        // A chord bound for the button still wins
+        fired(&mut engine, press(0, Key::KEY_LEFTSHIFT));
+        assert_eq!(
+            fired(&mut engine, press(1, Key::BTN_SIDE)),
+            Some(Command::Stack)
+        );
+    }
+
+    fn scroll(wheel: Wheel, notches: u32) -> Input {
+        Input::Wheel { wheel, notches }
+    }
+
+    #[test]
+    fn test_wheel_needs_its_gate() {
+        let mut engine = engine(&[("BTN_SIDE+WheelUp", "forward"), ("Ctrl+WheelUp", "last")]);
+
+        // Plain scrolling is left alone
+        assert_eq!(fired(&mut engine, scroll(Wheel::Up, 2)), None);
+
+        fired(&mut engine, press(1, Key::BTN_SIDE));
+        assert_eq!(
+            fired(&mut engine, scroll(Wheel::Up, 2)),
+            Some(Command::Forward { group: None })
+        );
+        fired(&mut engine, release(1, Key::BTN_SIDE));
+
+        // The gate and the wheel can be on different devices
+        fired(&mut engine, press(0, Key::KEY_LEFTCTRL));
+        let later = Instant::now() + Duration::from_secs(1);
+        assert_eq!(
+            fired_at(&mut engine, scroll(Wheel::Up, 2), later),
+            Some(Command::Last)
+        );
+    }
+
+    #[test]
+    fn test_wheel_gate_fires_on_release_unless_scrolled() {
+        let mut engine = engine(&[("BTN_SIDE", "backward"), ("BTN_SIDE+WheelUp", "forward")]);
+
+        // Scrolling while it's held replaces the button's own binding
+        assert_eq!(fired(&mut engine, press(1, Key::BTN_SIDE)), None);
+        assert_eq!(
+            fired(&mut engine, scroll(Wheel::Up, 2)),
+            Some(Command::Forward { group: None })
+        );
+        assert_eq!(fired(&mut engine, release(1, Key::BTN_SIDE)), None);
+
+        // A plain click still acts, once it's let go
+        assert_eq!(fired(&mut engine, press(1, Key::BTN_SIDE)), None);
+        assert_eq!(
+            fired(&mut engine, release(1, Key::BTN_SIDE)),
+            Some(Command::Backward { group: None })
+        );
+    }
+
+    #[test]
+    fn test_wheel_threshold_and_rate_limit() {
+        let mut engine = engine(&[("WheelRight", "forward"), ("WheelLeft", "backward")]);
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+
+        // Two notches one way make a step
+        assert_eq!(fired_at(&mut engine, scroll(Wheel::Right, 1), at(0)), None);
+        assert_eq!(
+            fired_at(&mut engine, scroll(Wheel::Right, 1), at(10)),
+            Some(Command::Forward { group: None })
+        );
+
+        // Changing direction starts counting again
+        assert_eq!(
+            fired_at(&mut engine, scroll(Wheel::Right, 1), at(200)),
+            None
+        );
+        assert_eq!(fired_at(&mut engine, scroll(Wheel::Left, 1), at(210)), None);
+        assert_eq!(
+            fired_at(&mut engine, scroll(Wheel::Left, 1), at(220)),
+            Some(Command::Backward { group: None })
+        );
+
+        // Too soon after the last step: dropped
+        assert_eq!(fired_at(&mut engine, scroll(Wheel::Left, 2), at(260)), None);
+        assert_eq!(
+            fired_at(&mut engine, scroll(Wheel::Left, 2), at(330)),
+            Some(Command::Backward { group: None })
+        );
+    }
+}
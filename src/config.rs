@@ -1,7 +1,12 @@
+use crate::bindings::{FocusRule, WheelSettings};
+use crate::cycle_state::{CycleMode, CycleOrder, CycleRules, UnlistedPlacement};
+use crate::window_manager::Backend;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
@@ -18,14 +23,48 @@ pub struct Config {
     pub forward_button: u16, // BTN_SIDE (mouse button 9)
     #[serde(default = "default_backward_button")]
     pub backward_button: u16, // BTN_EXTRA (mouse button 8)
+    #[serde(default)]
+    pub forward_button_focus: FocusRule, // "always", "eve_focused" or "eve_unfocused"
+    #[serde(default)]
+    pub backward_button_focus: FocusRule,
     #[serde(default = "default_show_overlay")]
     pub show_overlay: bool,
     #[serde(default = "default_mouse_device_name")]
     pub mouse_device_name: Option<String>,
     #[serde(default = "default_mouse_device_path")]
     pub mouse_device_path: Option<String>,
+    #[serde(default)]
+    pub mouse_group: Option<String>, // Mouse buttons only cycle this group
     #[serde(default = "default_minimize_inactive")]
     pub minimize_inactive: bool,
+    #[serde(default = "default_show_previews")]
+    pub show_previews: bool, // Live client thumbnails (X11 only)
+    #[serde(default = "default_preview_width")]
+    pub preview_width: u16,
+    #[serde(default = "default_preview_height")]
+    pub preview_height: u16,
+    #[serde(default = "default_preview_x")]
+    pub preview_x: i32,
+    #[serde(default = "default_preview_y")]
+    pub preview_y: i32,
+    #[serde(default)]
+    pub backend: Backend, // "auto" detects it from the session
+    #[serde(default)]
+    pub cycle_mode: CycleMode, // "order" or "mru" (Alt-Tab style)
+    #[serde(default = "default_mru_timeout_ms")]
+    pub mru_timeout_ms: u64, // MRU presses closer than this keep walking back
+    #[serde(default)]
+    pub excluded_characters: Vec<String>, // Never cycled to, switch still reaches them
+    #[serde(default)]
+    pub cycle_order: CycleOrder, // "detected", or "characters" for characters.txt order
+    #[serde(default)]
+    pub unlisted_placement: UnlistedPlacement, // Clients missing from characters.txt: "alphabetical" or "detected"
+    #[serde(default = "default_wheel_notches")]
+    pub wheel_notches: u32, // Wheel notches one way per binding step
+    #[serde(default = "default_wheel_interval_ms")]
+    pub wheel_interval_ms: u64, // Least time between wheel binding steps
+    #[serde(default, alias = "hotkeys")]
+    pub bindings: BTreeMap<String, String>, // Key or button to command, e.g. "Ctrl+F1" = "switch:1"
 }
 
 fn default_enable_mouse() -> bool {
@@ -56,8 +95,49 @@ fn default_minimize_inactive() -> bool {
     false
 }
 
+fn default_show_previews() -> bool {
+    false
+}
+
+fn default_preview_width() -> u16 {
+    320
+}
+
+fn default_preview_height() -> u16 {
+    180
+}
+
+fn default_preview_x() -> i32 {
+    240 // Just right of the overlay
+}
+
+fn default_preview_y() -> i32 {
+    10
+}
+
+fn default_mru_timeout_ms() -> u64 {
+    1000
+}
+
+fn default_wheel_notches() -> u32 {
+    1
+}
+
+fn default_wheel_interval_ms() -> u64 {
+    150
+}
+
+/// One character name per line; blank lines and `#` comments are skipped
+fn parse_character_list(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
 impl Config {
-    fn config_dir() -> PathBuf {
+    pub fn config_dir() -> PathBuf {
         let mut path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
         path.push("nicotine");
         path
@@ -80,13 +160,31 @@ impl Config {
             return None;
         }
 
-        fs::read_to_string(&path).ok().map(|contents| {
-            contents
-                .lines()
-                .map(|line| line.trim().to_string())
-                .filter(|line| !line.is_empty() && !line.starts_with('#'))
-                .collect()
-        })
+        fs::read_to_string(&path)
+            .ok()
+            .map(|contents| parse_character_list(&contents))
+    }
+
+    /// Load cycle groups from groups/<name>.txt, each in the characters.txt format
+    pub fn load_groups() -> BTreeMap<String, Vec<String>> {
+        let mut path = Self::config_dir();
+        path.push("groups");
+
+        let Ok(entries) = fs::read_dir(&path) else {
+            return BTreeMap::new();
+        };
+
+        entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "txt" {
+                    return None;
+                }
+                let name = path.file_stem()?.to_str()?.to_string();
+                let contents = fs::read_to_string(&path).ok()?;
+                Some((name, parse_character_list(&contents)))
+            })
+            .collect()
     }
 
     fn detect_display_size() -> (u32, u32) {
@@ -139,10 +237,27 @@ impl Config {
             enable_mouse_buttons: true,
             forward_button: 276,  // BTN_SIDE (button 9)
             backward_button: 275, // BTN_EXTRA (button 8)
+            forward_button_focus: FocusRule::EveFocused,
+            backward_button_focus: FocusRule::EveFocused,
             show_overlay: true,
             mouse_device_name: None,
             mouse_device_path: None,
+            mouse_group: None,
             minimize_inactive: false,
+            show_previews: false,
+            preview_width: 320,
+            preview_height: 180,
+            preview_x: 240,
+            preview_y: 10,
+            backend: Backend::Auto,
+            cycle_mode: CycleMode::Order,
+            mru_timeout_ms: 1000,
+            excluded_characters: Vec::new(),
+            cycle_order: CycleOrder::Detected,
+            unlisted_placement: UnlistedPlacement::Alphabetical,
+            wheel_notches: 1,
+            wheel_interval_ms: 150,
+            bindings: BTreeMap::new(),
         };
 
         // Save the generated config
@@ -172,10 +287,27 @@ impl Config {
             enable_mouse_buttons: true,
             forward_button: 276,
             backward_button: 275,
+            forward_button_focus: FocusRule::EveFocused,
+            backward_button_focus: FocusRule::EveFocused,
             show_overlay: true,
             mouse_device_name: None,
             mouse_device_path: None,
+            mouse_group: None,
             minimize_inactive: false,
+            show_previews: false,
+            preview_width: 320,
+            preview_height: 180,
+            preview_x: 240,
+            preview_y: 10,
+            backend: Backend::Auto,
+            cycle_mode: CycleMode::Order,
+            mru_timeout_ms: 1000,
+            excluded_characters: Vec::new(),
+            cycle_order: CycleOrder::Detected,
+            unlisted_placement: UnlistedPlacement::Alphabetical,
+            wheel_notches: 1,
+            wheel_interval_ms: 150,
+            bindings: BTreeMap::new(),
         };
 
         if let Some(parent) = config_path.parent() {
@@ -190,6 +322,45 @@ impl Config {
     pub fn eve_height_adjusted(&self) -> u32 {
         self.display_height - self.panel_height
     }
+
+    /// Which clients forward/backward visit and in what order, loading
+    /// characters.txt if the order comes from there
+    pub fn cycle_rules(&self) -> CycleRules {
+        CycleRules {
+            excluded: self.excluded_characters.clone(),
+            order: match self.cycle_order {
+                CycleOrder::Detected => None,
+                CycleOrder::Characters => Self::load_characters(),
+            },
+            unlisted: self.unlisted_placement,
+        }
+    }
+
+    pub fn mru_timeout(&self) -> Duration {
+        Duration::from_millis(self.mru_timeout_ms)
+    }
+
+    pub fn wheel_settings(&self) -> WheelSettings {
+        WheelSettings {
+            notches: self.wheel_notches.max(1),
+            interval: Duration::from_millis(self.wheel_interval_ms),
+        }
+    }
+}
+
+/// A config with only the required keys, followed by the TOML in `extra`
+#[cfg(test)]
+pub(crate) fn test_config(extra: &str) -> Config {
+    let required = r#"
+        display_width = 1920
+        display_height = 1080
+        panel_height = 0
+        eve_width = 1037
+        eve_height = 1080
+        overlay_x = 10.0
+        overlay_y = 10.0
+    "#;
+    toml::from_str(&format!("{}\n{}", required, extra)).unwrap()
 }
 
 #[cfg(test)]
@@ -209,10 +380,27 @@ mod tests {
             enable_mouse_buttons: true,
             forward_button: 276,
             backward_button: 275,
+            forward_button_focus: FocusRule::EveFocused,
+            backward_button_focus: FocusRule::EveFocused,
             show_overlay: true,
             mouse_device_name: None,
             mouse_device_path: None,
+            mouse_group: None,
             minimize_inactive: false,
+            show_previews: false,
+            preview_width: 320,
+            preview_height: 180,
+            preview_x: 240,
+            preview_y: 10,
+            backend: Backend::Auto,
+            cycle_mode: CycleMode::Order,
+            mru_timeout_ms: 1000,
+            excluded_characters: Vec::new(),
+            cycle_order: CycleOrder::Detected,
+            unlisted_placement: UnlistedPlacement::Alphabetical,
+            wheel_notches: 1,
+            wheel_interval_ms: 150,
+            bindings: BTreeMap::new(),
         };
 
         // Height should be: 1080 - 40 = 1040
@@ -232,15 +420,38 @@ mod tests {
             enable_mouse_buttons: true,
             forward_button: 276,
             backward_button: 275,
+            forward_button_focus: FocusRule::EveFocused,
+            backward_button_focus: FocusRule::EveFocused,
             show_overlay: true,
             mouse_device_name: None,
             mouse_device_path: None,
+            mouse_group: None,
             minimize_inactive: false,
+            show_previews: false,
+            preview_width: 320,
+            preview_height: 180,
+            preview_x: 240,
+            preview_y: 10,
+            backend: Backend::Auto,
+            cycle_mode: CycleMode::Order,
+            mru_timeout_ms: 1000,
+            excluded_characters: Vec::new(),
+            cycle_order: CycleOrder::Detected,
+            unlisted_placement: UnlistedPlacement::Alphabetical,
+            wheel_notches: 1,
+            wheel_interval_ms: 150,
+            bindings: BTreeMap::new(),
         };
 
         assert_eq!(config.eve_height_adjusted(), 1080);
     }
 
+    #[test]
+    fn test_parse_character_list() {
+        let contents = "# DPS\nAlpha\n\n  Beta  \n#Gamma\n";
+        assert_eq!(parse_character_list(contents), vec!["Alpha", "Beta"]);
+    }
+
     #[test]
     fn test_config_serialization() {
         let config = Config {
@@ -254,10 +465,27 @@ mod tests {
             enable_mouse_buttons: true,
             forward_button: 276,
             backward_button: 275,
+            forward_button_focus: FocusRule::EveFocused,
+            backward_button_focus: FocusRule::EveFocused,
             show_overlay: true,
             mouse_device_name: None,
             mouse_device_path: None,
+            mouse_group: None,
             minimize_inactive: false,
+            show_previews: false,
+            preview_width: 320,
+            preview_height: 180,
+            preview_x: 240,
+            preview_y: 10,
+            backend: Backend::Auto,
+            cycle_mode: CycleMode::Order,
+            mru_timeout_ms: 1000,
+            excluded_characters: Vec::new(),
+            cycle_order: CycleOrder::Detected,
+            unlisted_placement: UnlistedPlacement::Alphabetical,
+            wheel_notches: 1,
+            wheel_interval_ms: 150,
+            bindings: BTreeMap::new(),
         };
 
         let toml_str = toml::to_string(&config).unwrap();
@@ -1,13 +1,149 @@
-use crate::window_manager::{EveWindow, WindowManager};
+use crate::events::{Event, EventBus};
+use crate::runtime;
+use crate::window_manager::{EveWindow, WindowHandle, WindowManager};
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Characters remembered in the focus history
+const HISTORY_LIMIT: usize = 32;
+
+/// How forward/backward pick the next client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CycleMode {
+    /// Window list order (or characters.txt order when switching)
+    #[default]
+    Order,
+    /// Most recently used first, like Alt-Tab
+    Mru,
+}
+
+/// Reasons a targeted switch can fail, kept distinct so the daemon can report them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwitchError {
+    OutOfRange { target: usize, available: usize },
+    CharacterNotFound(String),
+    GroupNotFound(String),
+}
 
-const INDEX_FILE: &str = "/tmp/nicotine-index";
+impl fmt::Display for SwitchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SwitchError::OutOfRange { target, available } => {
+                write!(
+                    f,
+                    "Target {} is out of range (only {} available)",
+                    target, available
+                )
+            }
+            SwitchError::CharacterNotFound(name) => {
+                write!(f, "Character '{}' not found in active windows", name)
+            }
+            SwitchError::GroupNotFound(name) => {
+                write!(f, "No group '{}' (expected groups/{}.txt)", name, name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SwitchError {}
+
+/// Order forward/backward visit clients in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CycleOrder {
+    /// Whatever order the backend reports
+    #[default]
+    Detected,
+    /// characters.txt order, the same one switch uses
+    Characters,
+}
+
+/// Where clients missing from an explicit cycle order go
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnlistedPlacement {
+    /// After the listed ones, sorted by name
+    #[default]
+    Alphabetical,
+    /// After the listed ones, in detection order
+    Detected,
+}
+
+/// Which clients forward/backward visit, and in what order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CycleRules {
+    /// Skipped when cycling, still reachable with switch
+    pub excluded: Vec<String>,
+    /// Explicit order by character, or None for detection order
+    pub order: Option<Vec<String>>,
+    pub unlisted: UnlistedPlacement,
+}
+
+impl CycleRules {
+    fn arrange(&self, windows: &mut [EveWindow]) {
+        let Some(order) = &self.order else {
+            return;
+        };
+
+        // Stable, so ties keep detection order
+        windows.sort_by_cached_key(|window| {
+            match order.iter().position(|name| *name == window.title) {
+                Some(position) => (0, position, String::new()),
+                None => match self.unlisted {
+                    UnlistedPlacement::Alphabetical => (1, 0, window.title.clone()),
+                    UnlistedPlacement::Detected => (1, 0, String::new()),
+                },
+            }
+        });
+    }
+
+    fn is_excluded(&self, window: &EveWindow) -> bool {
+        self.excluded.contains(&window.title)
+    }
+}
+
+/// What a cycling command walks through
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CycleScope {
+    /// Every client, in the given mode
+    All(CycleMode),
+    /// The open members of a named group, in the group's order
+    Group(String),
+}
+
+/// Move `steps` through `len` members, starting from member `Ok(position)`,
+/// or from outside the members just before member `Err(next)`
+fn step_through(len: usize, from: std::result::Result<usize, usize>, steps: isize) -> usize {
+    let len = len as isize;
+    let position = match from {
+        Ok(position) => position as isize + steps,
+        Err(next) if steps > 0 => next as isize + steps - 1,
+        Err(next) => next as isize + steps,
+    };
+    position.rem_euclid(len) as usize
+}
+
+/// A run of MRU presses close enough together to keep walking back in history
+struct MruWalk {
+    /// Focus history when the walk started, most recent first
+    order: Vec<String>,
+    depth: isize,
+    deadline: Instant,
+}
 
 pub struct CycleState {
     current_index: usize,
     windows: Vec<EveWindow>,
+    events: Option<EventBus>,
+    /// Characters by last focus, most recent first. Keyed by title so it
+    /// survives window list refreshes and client restarts.
+    history: Vec<String>,
+    walk: Option<MruWalk>,
+    rules: CycleRules,
 }
 
 impl CycleState {
@@ -15,63 +151,245 @@ impl CycleState {
         Self {
             current_index: 0,
             windows: Vec::new(),
+            events: None,
+            history: Vec::new(),
+            walk: None,
+            rules: CycleRules::default(),
         }
     }
 
-    pub fn update_windows(&mut self, windows: Vec<EveWindow>) {
+    /// Change which clients cycling visits and their order
+    pub fn set_rules(&mut self, rules: CycleRules) {
+        self.rules = rules;
+        let windows = self.windows.clone();
+        self.update_windows(windows);
+    }
+
+    /// Create a state that publishes client and focus changes to `events`
+    pub fn with_events(events: EventBus) -> Self {
+        Self {
+            events: Some(events),
+            ..Self::new()
+        }
+    }
+
+    pub fn update_windows(&mut self, mut windows: Vec<EveWindow>) {
+        self.rules.arrange(&mut windows);
+
+        let previous_index = self.current_index;
+        let current_id = self.windows.get(self.current_index).map(|w| w.id.clone());
+
+        if let Some(events) = &self.events {
+            let same = |a: &EveWindow, b: &EveWindow| a.id == b.id && a.title == b.title;
+
+            for window in &self.windows {
+                if !windows.iter().any(|w| same(w, window)) {
+                    events.publish(Event::ClientRemoved {
+                        id: window.id.clone(),
+                        title: window.title.clone(),
+                    });
+                }
+            }
+            for window in &windows {
+                if !self.windows.iter().any(|w| same(w, window)) {
+                    events.publish(Event::ClientAdded {
+                        id: window.id.clone(),
+                        title: window.title.clone(),
+                    });
+                }
+            }
+        }
+
         self.windows = windows;
-        // Clamp current index
-        if self.current_index >= self.windows.len() && !self.windows.is_empty() {
+
+        // Follow the current window if it is still there, otherwise clamp
+        if let Some(index) = current_id.and_then(|id| self.windows.iter().position(|w| w.id == id))
+        {
+            self.current_index = index;
+        } else if self.current_index >= self.windows.len() && !self.windows.is_empty() {
             self.current_index = 0;
         }
+
+        if self.current_index != previous_index {
+            self.publish_active();
+        }
     }
 
     pub fn cycle_forward(&mut self, wm: &dyn WindowManager, minimize_inactive: bool) -> Result<()> {
-        if self.windows.is_empty() {
+        self.cycle_by(1, wm, minimize_inactive)
+    }
+
+    pub fn cycle_backward(
+        &mut self,
+        wm: &dyn WindowManager,
+        minimize_inactive: bool,
+    ) -> Result<()> {
+        self.cycle_by(-1, wm, minimize_inactive)
+    }
+
+    /// Move `steps` clients forward (negative = backward), wrapping around.
+    /// A burst of presses can be applied in one go so only the final client
+    /// gets activated.
+    pub fn cycle_by(
+        &mut self,
+        steps: isize,
+        wm: &dyn WindowManager,
+        minimize_inactive: bool,
+    ) -> Result<()> {
+        let members: Vec<usize> = (0..self.windows.len())
+            .filter(|&index| !self.rules.is_excluded(&self.windows[index]))
+            .collect();
+
+        if members.is_empty() || steps == 0 {
             return Ok(());
         }
 
-        let previous_index = self.current_index;
-        self.current_index = (self.current_index + 1) % self.windows.len();
-        self.write_index();
+        self.end_walk();
 
-        let new_window_id = self.windows[self.current_index].id;
+        // From an excluded client, count from the next one in order
+        let from = members.binary_search(&self.current_index);
+        let target_index = members[step_through(members.len(), from, steps)];
+        self.focus_index(target_index, wm, minimize_inactive)
+    }
 
-        if minimize_inactive {
-            // Restore new window first (in case it was minimized)
-            let _ = wm.restore_window(new_window_id);
-        }
+    /// Move `steps` clients within `group`, in the group's order. From a client
+    /// outside the group, forward starts at its first member and backward at its last.
+    pub fn cycle_group(
+        &mut self,
+        steps: isize,
+        group: &[String],
+        wm: &dyn WindowManager,
+        minimize_inactive: bool,
+    ) -> Result<()> {
+        let members: Vec<usize> = group
+            .iter()
+            .filter_map(|name| self.windows.iter().position(|w| w.title == *name))
+            .collect();
 
-        wm.activate_window(new_window_id)?;
+        if members.is_empty() || steps == 0 {
+            return Ok(());
+        }
 
-        if minimize_inactive && previous_index != self.current_index {
-            // Minimize the previous window after activating the new one
-            let previous_window_id = self.windows[previous_index].id;
-            let _ = wm.minimize_window(previous_window_id);
+        let from = members
+            .iter()
+            .position(|&index| index == self.current_index)
+            .ok_or(0);
+        let target_index = members[step_through(members.len(), from, steps)];
+        if target_index == self.current_index {
+            return Ok(());
         }
 
-        Ok(())
+        self.end_walk();
+        self.focus_index(target_index, wm, minimize_inactive)
     }
 
-    pub fn cycle_backward(
+    /// Walk the focus history: one step goes back to the previous client,
+    /// further steps within `timeout` of the last one go further back.
+    /// Negative steps walk towards the most recent client again.
+    pub fn cycle_recent(
         &mut self,
+        steps: isize,
         wm: &dyn WindowManager,
         minimize_inactive: bool,
+        timeout: Duration,
     ) -> Result<()> {
-        if self.windows.is_empty() {
+        if self.windows.is_empty() || steps == 0 {
             return Ok(());
         }
 
-        let previous_index = self.current_index;
-        if self.current_index == 0 {
-            self.current_index = self.windows.len() - 1;
-        } else {
-            self.current_index -= 1;
+        let now = Instant::now();
+        let mut walk = match self.walk.take() {
+            Some(walk) if now < walk.deadline => walk,
+            expired => {
+                self.walk = expired;
+                self.end_walk();
+                self.record_focus();
+                MruWalk {
+                    order: self.recent_titles(),
+                    depth: 0,
+                    deadline: now,
+                }
+            }
+        };
+
+        walk.depth = (walk.depth + steps).rem_euclid(walk.order.len() as isize);
+        walk.deadline = now + timeout;
+
+        // A client that closed mid-walk is skipped, not resurrected
+        let target_index = self
+            .windows
+            .iter()
+            .position(|w| w.title == walk.order[walk.depth as usize]);
+        self.walk = Some(walk);
+
+        match target_index {
+            Some(index) if index != self.current_index => {
+                self.focus_index(index, wm, minimize_inactive)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Titles of the open clients cycling visits, most recently focused first,
+    /// then the never-focused ones in window order. The current client always
+    /// comes first, even if excluded, as that's where a walk starts.
+    fn recent_titles(&self) -> Vec<String> {
+        let mut titles: Vec<String> = Vec::new();
+        let open = self.windows.iter().map(|w| &w.title);
+        let current = self.windows.get(self.current_index).map(|w| &w.title);
+
+        for title in current.into_iter().chain(&self.history).chain(open) {
+            let cyclable = self
+                .windows
+                .iter()
+                .any(|w| w.title == *title && !self.rules.is_excluded(w));
+            if (cyclable || Some(title) == current) && !titles.contains(title) {
+                titles.push(title.clone());
+            }
+        }
+
+        titles
+    }
+
+    /// Move the current client to the front of the history, unless an MRU
+    /// walk is still choosing one
+    fn record_focus(&mut self) {
+        if self.walk.is_some() {
+            return;
+        }
+
+        if let Some(window) = self.windows.get(self.current_index) {
+            self.history.retain(|title| *title != window.title);
+            self.history.insert(0, window.title.clone());
+            self.history.truncate(HISTORY_LIMIT);
+        }
+    }
+
+    /// Stop an MRU walk, settling on the client it reached
+    fn end_walk(&mut self) {
+        if self.walk.take().is_some() {
+            self.record_focus();
         }
+    }
+
+    /// Make `target_index` the current client and activate it, minimizing the
+    /// previous one if asked to
+    fn focus_index(
+        &mut self,
+        target_index: usize,
+        wm: &dyn WindowManager,
+        minimize_inactive: bool,
+    ) -> Result<()> {
+        let minimize_inactive = minimize_inactive && wm.capabilities().minimize;
+
+        let previous_index = self.current_index;
+        self.current_index = target_index;
 
         self.write_index();
+        self.publish_active();
+        self.record_focus();
 
-        let new_window_id = self.windows[self.current_index].id;
+        let new_window_id = &self.windows[self.current_index].id;
 
         if minimize_inactive {
             // Restore new window first (in case it was minimized)
@@ -82,25 +400,31 @@ impl CycleState {
 
         if minimize_inactive && previous_index != self.current_index {
             // Minimize the previous window after activating the new one
-            let previous_window_id = self.windows[previous_index].id;
+            let previous_window_id = &self.windows[previous_index].id;
             let _ = wm.minimize_window(previous_window_id);
         }
 
         Ok(())
     }
 
+    fn publish_active(&self) {
+        if let (Some(events), Some(window)) = (&self.events, self.windows.get(self.current_index)) {
+            events.publish(Event::ActiveChanged {
+                index: self.current_index,
+                id: window.id.clone(),
+                title: window.title.clone(),
+            });
+        }
+    }
+
     fn write_index(&self) {
-        let _ = fs::write(INDEX_FILE, self.current_index.to_string());
+        let _ = fs::write(runtime::index_file(), self.current_index.to_string());
     }
 
     pub fn read_index_from_file() -> Option<usize> {
-        if Path::new(INDEX_FILE).exists() {
-            fs::read_to_string(INDEX_FILE)
-                .ok()
-                .and_then(|s| s.trim().parse().ok())
-        } else {
-            None
-        }
+        fs::read_to_string(runtime::index_file())
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
     }
 
     pub fn get_windows(&self) -> &[EveWindow] {
@@ -117,12 +441,15 @@ impl CycleState {
         }
     }
 
-    pub fn sync_with_active(&mut self, active_window: u32) {
+    pub fn sync_with_active(&mut self, active_window: &WindowHandle) {
         // Find which window is active and update current_index
-        for (i, window) in self.windows.iter().enumerate() {
-            if window.id == active_window {
-                self.current_index = i;
-                break;
+        if let Some(index) = self.windows.iter().position(|w| w.id == *active_window) {
+            if index != self.current_index {
+                // Focus moved outside nicotine, which ends any MRU walk
+                self.end_walk();
+                self.current_index = index;
+                self.publish_active();
+                self.record_focus();
             }
         }
     }
@@ -145,11 +472,11 @@ impl CycleState {
             // Use character order from characters.txt
             let target_idx = target - 1; // Convert to 0-indexed
             if target_idx >= characters.len() {
-                anyhow::bail!(
-                    "Target {} is out of range (only {} characters configured)",
+                return Err(SwitchError::OutOfRange {
                     target,
-                    characters.len()
-                );
+                    available: characters.len(),
+                }
+                .into());
             }
 
             let target_name = &characters[target_idx];
@@ -158,18 +485,16 @@ impl CycleState {
             self.windows
                 .iter()
                 .position(|w| w.title == *target_name)
-                .ok_or_else(|| {
-                    anyhow::anyhow!("Character '{}' not found in active windows", target_name)
-                })?
+                .ok_or_else(|| SwitchError::CharacterNotFound(target_name.clone()))?
         } else {
             // Fall back to window list order
             let target_idx = target - 1; // Convert to 0-indexed
             if target_idx >= self.windows.len() {
-                anyhow::bail!(
-                    "Target {} is out of range (only {} windows)",
+                return Err(SwitchError::OutOfRange {
                     target,
-                    self.windows.len()
-                );
+                    available: self.windows.len(),
+                }
+                .into());
             }
             target_idx
         };
@@ -179,24 +504,29 @@ impl CycleState {
             return Ok(());
         }
 
-        let previous_index = self.current_index;
-        self.current_index = target_index;
-        self.write_index();
-
-        let new_window_id = self.windows[self.current_index].id;
-
-        if minimize_inactive {
-            let _ = wm.restore_window(new_window_id);
-        }
+        self.end_walk();
+        self.focus_index(target_index, wm, minimize_inactive)
+    }
 
-        wm.activate_window(new_window_id)?;
+    /// Switch to the client of `character`, wherever it is in the cycle order
+    pub fn switch_to_character(
+        &mut self,
+        character: &str,
+        wm: &dyn WindowManager,
+        minimize_inactive: bool,
+    ) -> Result<()> {
+        let target_index = self
+            .windows
+            .iter()
+            .position(|w| w.title == character)
+            .ok_or_else(|| SwitchError::CharacterNotFound(character.to_string()))?;
 
-        if minimize_inactive {
-            let previous_window_id = self.windows[previous_index].id;
-            let _ = wm.minimize_window(previous_window_id);
+        if target_index == self.current_index {
+            return Ok(());
         }
 
-        Ok(())
+        self.end_walk();
+        self.focus_index(target_index, wm, minimize_inactive)
     }
 }
 
@@ -206,7 +536,7 @@ mod tests {
 
     fn create_test_window(id: u32, title: &str) -> EveWindow {
         EveWindow {
-            id,
+            id: id.into(),
             title: title.to_string(),
         }
     }
@@ -269,11 +599,11 @@ mod tests {
         state.update_windows(windows);
 
         // Sync with window id 300
-        state.sync_with_active(300);
+        state.sync_with_active(&300u32.into());
         assert_eq!(state.get_current_index(), 2);
 
         // Sync with window id 100
-        state.sync_with_active(100);
+        state.sync_with_active(&100u32.into());
         assert_eq!(state.get_current_index(), 0);
     }
 
@@ -288,7 +618,7 @@ mod tests {
         state.current_index = 1;
 
         // Sync with non-existent window - index shouldn't change
-        state.sync_with_active(999);
+        state.sync_with_active(&999u32.into());
         assert_eq!(state.get_current_index(), 1);
     }
 
@@ -303,8 +633,8 @@ mod tests {
 
         let returned_windows = state.get_windows();
         assert_eq!(returned_windows.len(), 2);
-        assert_eq!(returned_windows[0].id, 1);
-        assert_eq!(returned_windows[1].id, 2);
+        assert_eq!(returned_windows[0].id, WindowHandle::from(1u32));
+        assert_eq!(returned_windows[1].id, WindowHandle::from(2u32));
     }
 
     #[test]
@@ -328,7 +658,7 @@ mod tests {
         assert_eq!(state.get_current_index(), 0);
 
         // Syncing with the only window should work
-        state.sync_with_active(1);
+        state.sync_with_active(&1u32.into());
         assert_eq!(state.get_current_index(), 0);
     }
 
@@ -362,28 +692,56 @@ mod tests {
 
     // Mock WindowManager for testing switch_to
     struct MockWindowManager {
-        activated_windows: std::sync::Mutex<Vec<u32>>,
+        activated_windows: std::sync::Mutex<Vec<WindowHandle>>,
+        minimized_windows: std::sync::Mutex<Vec<WindowHandle>>,
+        can_minimize: bool,
     }
 
     impl MockWindowManager {
         fn new() -> Self {
             Self {
                 activated_windows: std::sync::Mutex::new(Vec::new()),
+                minimized_windows: std::sync::Mutex::new(Vec::new()),
+                can_minimize: true,
             }
         }
 
-        fn get_activated(&self) -> Vec<u32> {
-            self.activated_windows.lock().unwrap().clone()
+        fn get_activated(&self) -> Vec<u64> {
+            self.activated_windows
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(WindowHandle::as_u64)
+                .collect()
         }
     }
 
     impl WindowManager for MockWindowManager {
+        fn name(&self) -> &'static str {
+            "Mock"
+        }
+
+        fn capabilities(&self) -> crate::window_manager::Capabilities {
+            crate::window_manager::Capabilities {
+                move_windows: true,
+                resize_windows: true,
+                minimize: self.can_minimize,
+                stacking: true,
+                events: false,
+                thumbnails: false,
+                overlay_dragging: false,
+            }
+        }
+
         fn get_eve_windows(&self) -> anyhow::Result<Vec<EveWindow>> {
             Ok(vec![])
         }
 
-        fn activate_window(&self, window_id: u32) -> anyhow::Result<()> {
-            self.activated_windows.lock().unwrap().push(window_id);
+        fn activate_window(&self, window_id: &WindowHandle) -> anyhow::Result<()> {
+            self.activated_windows
+                .lock()
+                .unwrap()
+                .push(window_id.clone());
             Ok(())
         }
 
@@ -395,19 +753,23 @@ mod tests {
             Ok(())
         }
 
-        fn get_active_window(&self) -> anyhow::Result<u32> {
-            Ok(0)
+        fn get_active_window(&self) -> anyhow::Result<WindowHandle> {
+            Ok(WindowHandle::Id(0))
         }
 
-        fn find_window_by_title(&self, _title: &str) -> anyhow::Result<Option<u32>> {
+        fn find_window_by_title(&self, _title: &str) -> anyhow::Result<Option<WindowHandle>> {
             Ok(None)
         }
 
-        fn minimize_window(&self, _window_id: u32) -> anyhow::Result<()> {
+        fn minimize_window(&self, window_id: &WindowHandle) -> anyhow::Result<()> {
+            self.minimized_windows
+                .lock()
+                .unwrap()
+                .push(window_id.clone());
             Ok(())
         }
 
-        fn restore_window(&self, _window_id: u32) -> anyhow::Result<()> {
+        fn restore_window(&self, _window_id: &WindowHandle) -> anyhow::Result<()> {
             Ok(())
         }
     }
@@ -430,6 +792,179 @@ mod tests {
         assert_eq!(wm.get_activated(), vec![200]);
     }
 
+    #[test]
+    fn test_switch_to_character() {
+        let mut state = CycleState::new();
+        state.update_windows(vec![
+            create_test_window(100, "Alpha"),
+            create_test_window(200, "Beta"),
+        ]);
+
+        let wm = MockWindowManager::new();
+
+        state.switch_to_character("Beta", &wm, false).unwrap();
+        assert_eq!(state.get_current_index(), 1);
+        assert_eq!(wm.get_activated(), vec![200]);
+
+        let err = state.switch_to_character("Gamma", &wm, false).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<SwitchError>(),
+            Some(&SwitchError::CharacterNotFound("Gamma".to_string()))
+        );
+    }
+
+    fn titles(state: &CycleState) -> Vec<&str> {
+        state
+            .get_windows()
+            .iter()
+            .map(|w| w.title.as_str())
+            .collect()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_cycle_order_places_unlisted_clients() {
+        let detected = vec![
+            create_test_window(100, "Zulu"),
+            create_test_window(200, "Beta"),
+            create_test_window(300, "Main"),
+            create_test_window(400, "Alpha"),
+        ];
+
+        let mut state = CycleState::new();
+        state.set_rules(CycleRules {
+            order: Some(names(&["Main", "Offline", "Beta"])),
+            ..CycleRules::default()
+        });
+        state.update_windows(detected.clone());
+        assert_eq!(titles(&state), vec!["Main", "Beta", "Alpha", "Zulu"]);
+
+        state.set_rules(CycleRules {
+            order: Some(names(&["Main", "Offline", "Beta"])),
+            unlisted: UnlistedPlacement::Detected,
+            ..CycleRules::default()
+        });
+        state.update_windows(detected.clone());
+        assert_eq!(titles(&state), vec!["Main", "Beta", "Zulu", "Alpha"]);
+
+        // No explicit order keeps detection order
+        state.set_rules(CycleRules::default());
+        state.update_windows(detected);
+        assert_eq!(titles(&state), vec!["Zulu", "Beta", "Main", "Alpha"]);
+    }
+
+    #[test]
+    fn test_excluded_clients_are_skipped_but_switchable() {
+        let mut state = CycleState::new();
+        state.set_rules(CycleRules {
+            excluded: names(&["Market"]),
+            ..CycleRules::default()
+        });
+        state.update_windows(vec![
+            create_test_window(100, "Alpha"),
+            create_test_window(200, "Market"),
+            create_test_window(300, "Gamma"),
+        ]);
+
+        let wm = MockWindowManager::new();
+        state.cycle_forward(&wm, false).unwrap();
+        state.cycle_forward(&wm, false).unwrap();
+        assert_eq!(wm.get_activated(), vec![300, 100]);
+
+        // Switch still gets there, and cycling carries on from its place
+        state.switch_to(2, &wm, false, None).unwrap();
+        state.cycle_forward(&wm, false).unwrap();
+        state.switch_to(2, &wm, false, None).unwrap();
+        state.cycle_backward(&wm, false).unwrap();
+        assert_eq!(wm.get_activated(), vec![300, 100, 200, 300, 200, 100]);
+    }
+
+    #[test]
+    fn test_mru_skips_excluded_clients() {
+        let mut state = state_with_history();
+        state.set_rules(CycleRules {
+            excluded: names(&["Alpha"]),
+            ..CycleRules::default()
+        });
+
+        // Alpha was the previous client, but Beta is the previous one cycling visits
+        let wm = MockWindowManager::new();
+        state.cycle_recent(1, &wm, false, Duration::ZERO).unwrap();
+        assert_eq!(wm.get_activated(), vec![200]);
+    }
+
+    #[test]
+    fn test_cycle_group_only_visits_members() {
+        let mut state = CycleState::new();
+        state.update_windows(vec![
+            create_test_window(100, "Alpha"),
+            create_test_window(200, "Beta"),
+            create_test_window(300, "Gamma"),
+            create_test_window(400, "Delta"),
+        ]);
+        // Group order wins over window order; closed members are skipped
+        let dps = [
+            "Delta".to_string(),
+            "Offline".to_string(),
+            "Beta".to_string(),
+        ];
+
+        let wm = MockWindowManager::new();
+        state.cycle_group(1, &dps, &wm, false).unwrap();
+        state.cycle_group(1, &dps, &wm, false).unwrap();
+        state.cycle_group(1, &dps, &wm, false).unwrap();
+        assert_eq!(wm.get_activated(), vec![400, 200, 400]);
+    }
+
+    #[test]
+    fn test_cycle_group_backward_from_outside() {
+        let mut state = CycleState::new();
+        state.update_windows(vec![
+            create_test_window(100, "Alpha"),
+            create_test_window(200, "Beta"),
+            create_test_window(300, "Gamma"),
+        ]);
+        let logi = ["Beta".to_string(), "Gamma".to_string()];
+
+        let wm = MockWindowManager::new();
+        state.cycle_group(-1, &logi, &wm, false).unwrap();
+        assert_eq!(wm.get_activated(), vec![300]);
+
+        // A group with nobody online is a no-op
+        state
+            .cycle_group(1, &["Offline".to_string()], &wm, false)
+            .unwrap();
+        assert_eq!(state.get_current_index(), 2);
+    }
+
+    #[test]
+    fn test_minimize_inactive_needs_backend_support() {
+        let mut state = CycleState::new();
+        state.update_windows(vec![
+            create_test_window(100, "Alpha"),
+            create_test_window(200, "Beta"),
+        ]);
+
+        let wm = MockWindowManager::new();
+        state.cycle_forward(&wm, true).unwrap();
+        assert_eq!(
+            *wm.minimized_windows.lock().unwrap(),
+            vec![WindowHandle::Id(100)]
+        );
+
+        // Without minimize support the option is ignored, not half-applied
+        let wm = MockWindowManager {
+            can_minimize: false,
+            ..MockWindowManager::new()
+        };
+        state.cycle_forward(&wm, true).unwrap();
+        assert_eq!(wm.get_activated(), vec![100]);
+        assert!(wm.minimized_windows.lock().unwrap().is_empty());
+    }
+
     #[test]
     fn test_switch_to_with_character_order() {
         let mut state = CycleState::new();
@@ -484,7 +1019,13 @@ mod tests {
 
         // Switch to target 5 when only 2 windows exist
         let result = state.switch_to(5, &wm, false, None);
-        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<SwitchError>(),
+            Some(&SwitchError::OutOfRange {
+                target: 5,
+                available: 2
+            })
+        );
     }
 
     #[test]
@@ -503,7 +1044,10 @@ mod tests {
 
         // Switch to target 3 (Gamma) - not logged in
         let result = state.switch_to(3, &wm, false, Some(&char_order));
-        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<SwitchError>(),
+            Some(&SwitchError::CharacterNotFound("Gamma".to_string()))
+        );
     }
 
     #[test]
@@ -529,4 +1073,195 @@ mod tests {
         state.switch_to(1, &wm, false, None).unwrap();
         assert!(wm.get_activated().is_empty());
     }
+
+    #[test]
+    fn test_update_windows_publishes_added_and_removed() {
+        let events = EventBus::new();
+        let rx = events.subscribe();
+        let mut state = CycleState::with_events(events);
+
+        state.update_windows(vec![create_test_window(100, "Alpha")]);
+        state.update_windows(vec![create_test_window(200, "Beta")]);
+
+        let received: Vec<Event> = rx.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![
+                Event::ClientAdded {
+                    id: 100u32.into(),
+                    title: "Alpha".to_string()
+                },
+                Event::ClientRemoved {
+                    id: 100u32.into(),
+                    title: "Alpha".to_string()
+                },
+                Event::ClientAdded {
+                    id: 200u32.into(),
+                    title: "Beta".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_update_windows_follows_current_window() {
+        let events = EventBus::new();
+        let mut state = CycleState::with_events(events.clone());
+        state.update_windows(vec![
+            create_test_window(100, "Alpha"),
+            create_test_window(200, "Beta"),
+        ]);
+        state.current_index = 1;
+
+        let rx = events.subscribe();
+
+        // A new client detected ahead of Beta shifts its position
+        state.update_windows(vec![
+            create_test_window(300, "Gamma"),
+            create_test_window(100, "Alpha"),
+            create_test_window(200, "Beta"),
+        ]);
+
+        assert_eq!(state.get_current_index(), 2);
+        assert!(rx.try_iter().any(|event| event
+            == Event::ActiveChanged {
+                index: 2,
+                id: 200u32.into(),
+                title: "Beta".to_string()
+            }));
+    }
+
+    #[test]
+    fn test_cycle_publishes_active_changed() {
+        let events = EventBus::new();
+        let mut state = CycleState::with_events(events.clone());
+        state.update_windows(vec![
+            create_test_window(100, "Alpha"),
+            create_test_window(200, "Beta"),
+        ]);
+
+        let rx = events.subscribe();
+        let wm = MockWindowManager::new();
+        state.cycle_forward(&wm, false).unwrap();
+
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            Event::ActiveChanged {
+                index: 1,
+                id: 200u32.into(),
+                title: "Beta".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_cycle_by_wraps_in_both_directions() {
+        let mut state = CycleState::new();
+        state.update_windows(vec![
+            create_test_window(100, "Alpha"),
+            create_test_window(200, "Beta"),
+            create_test_window(300, "Gamma"),
+        ]);
+
+        let wm = MockWindowManager::new();
+
+        // Three forward presses coalesced with one backward press
+        state.cycle_by(2, &wm, false).unwrap();
+        assert_eq!(state.get_current_index(), 2);
+
+        state.cycle_by(-4, &wm, false).unwrap();
+        assert_eq!(state.get_current_index(), 1);
+
+        // Only the final client of each burst is activated
+        assert_eq!(wm.get_activated(), vec![300, 200]);
+    }
+
+    #[test]
+    fn test_cycle_by_zero_does_nothing() {
+        let mut state = CycleState::new();
+        state.update_windows(vec![create_test_window(100, "Alpha")]);
+
+        let wm = MockWindowManager::new();
+        state.cycle_by(0, &wm, false).unwrap();
+
+        assert!(wm.get_activated().is_empty());
+    }
+
+    /// Alpha, Beta and Gamma, focused in that order (Gamma most recent)
+    fn state_with_history() -> CycleState {
+        let mut state = CycleState::new();
+        state.update_windows(vec![
+            create_test_window(100, "Alpha"),
+            create_test_window(200, "Beta"),
+            create_test_window(300, "Gamma"),
+        ]);
+
+        let wm = MockWindowManager::new();
+        state.switch_to(2, &wm, false, None).unwrap();
+        state.switch_to(1, &wm, false, None).unwrap();
+        state.switch_to(3, &wm, false, None).unwrap();
+        state
+    }
+
+    #[test]
+    fn test_cycle_recent_toggles_between_two_clients() {
+        let mut state = state_with_history();
+        let wm = MockWindowManager::new();
+
+        // Presses far apart each go back to the previous client
+        state.cycle_recent(1, &wm, false, Duration::ZERO).unwrap();
+        state.cycle_recent(1, &wm, false, Duration::ZERO).unwrap();
+        state.cycle_recent(1, &wm, false, Duration::ZERO).unwrap();
+
+        assert_eq!(wm.get_activated(), vec![100, 300, 100]);
+    }
+
+    #[test]
+    fn test_cycle_recent_walks_back_on_quick_presses() {
+        let mut state = state_with_history();
+        let wm = MockWindowManager::new();
+        let timeout = Duration::from_secs(60);
+
+        state.cycle_recent(1, &wm, false, timeout).unwrap();
+        state.cycle_recent(1, &wm, false, timeout).unwrap();
+        // Wraps around to where the walk started
+        state.cycle_recent(1, &wm, false, timeout).unwrap();
+        // And back again
+        state.cycle_recent(-1, &wm, false, timeout).unwrap();
+
+        assert_eq!(wm.get_activated(), vec![100, 200, 300, 200]);
+    }
+
+    #[test]
+    fn test_walk_settles_when_focus_moves_elsewhere() {
+        let mut state = state_with_history();
+        let wm = MockWindowManager::new();
+
+        // Walk back to Beta, then the user clicks Alpha
+        state
+            .cycle_recent(2, &wm, false, Duration::from_secs(60))
+            .unwrap();
+        state.sync_with_active(&100u32.into());
+
+        // Beta counts as used, so it's now the previous client
+        state.cycle_recent(1, &wm, false, Duration::ZERO).unwrap();
+        assert_eq!(wm.get_activated(), vec![200, 200]);
+    }
+
+    #[test]
+    fn test_history_survives_refresh() {
+        let mut state = state_with_history();
+
+        // Clients restarted: new ids, new order, same characters
+        state.update_windows(vec![
+            create_test_window(301, "Gamma"),
+            create_test_window(101, "Alpha"),
+            create_test_window(201, "Beta"),
+        ]);
+        state.sync_with_active(&301u32.into());
+
+        let wm = MockWindowManager::new();
+        state.cycle_recent(1, &wm, false, Duration::ZERO).unwrap();
+        assert_eq!(wm.get_activated(), vec![101]);
+    }
 }
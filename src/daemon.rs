@@ -1,43 +1,80 @@
+use crate::bindings::{self, Binding, BindingEngine, Chord, FocusRule, Input, Trigger};
 use crate::config::Config;
-use crate::cycle_state::CycleState;
+use crate::cycle_state::{CycleMode, CycleScope, CycleState, SwitchError};
+use crate::events::{Event, EventBus};
+use crate::input_watcher::{InputWatcher, Wanted};
+use crate::keyboard_listener::KeyboardListener;
 use crate::mouse_listener::MouseListener;
-use crate::window_manager::WindowManager;
-use anyhow::Result;
+use crate::preview::Previews;
+use crate::protocol::{ClientInfo, Command, ErrorBody, Request, Response, StatusInfo};
+use crate::runtime;
+use crate::window_manager::{WindowEvent, WindowHandle, WindowManager};
+use anyhow::{Context, Result};
+use nix::errno::Errno;
+use nix::sys::signal::{self, SigSet, SigmaskHow, Signal};
+use nix::unistd::Pid;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::MetadataExt;
 use std::os::unix::net::{UnixListener, UnixStream};
-use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-const SOCKET_PATH: &str = "/tmp/nicotine.sock";
-
-#[derive(Debug)]
-pub enum Command {
-    Forward,
-    Backward,
-    Switch(usize),
-    Refresh,
-    Quit,
-}
-
-impl Command {
-    pub fn from_str(s: &str) -> Option<Self> {
-        let s = s.trim();
-        match s {
-            "forward" => Some(Command::Forward),
-            "backward" => Some(Command::Backward),
-            "refresh" => Some(Command::Refresh),
-            "quit" => Some(Command::Quit),
-            _ => {
-                // Check for switch:N format
-                if let Some(num_str) = s.strip_prefix("switch:") {
-                    if let Ok(num) = num_str.parse::<usize>() {
-                        return Some(Command::Switch(num));
-                    }
-                }
-                None
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a subscriber may leave an event unread before it's dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a lookup of the overlay's window is trusted. Finding it by title
+/// lists every window, which is too slow to do on each button press.
+const OVERLAY_LOOKUP_TTL: Duration = Duration::from_secs(10);
+
+fn shutdown_signals() -> SigSet {
+    let mut signals = SigSet::empty();
+    signals.add(Signal::SIGTERM);
+    signals.add(Signal::SIGINT);
+    signals.add(Signal::SIGHUP);
+    signals
+}
+
+/// Block the shutdown signals so they can be handled by the daemon's signal
+/// thread. Must be called before any other thread is spawned, as threads
+/// inherit the signal mask of their parent.
+pub fn block_shutdown_signals() -> Result<()> {
+    signal::pthread_sigmask(SigmaskHow::SIG_BLOCK, Some(&shutdown_signals()), None)?;
+    Ok(())
+}
+
+/// Tear-down shared by the `quit` command and the signal thread
+#[derive(Clone)]
+struct Shutdown {
+    wm: Arc<dyn WindowManager>,
+    state: Arc<Mutex<CycleState>>,
+    restore_minimized: Arc<AtomicBool>,
+    running: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    fn run(&self) -> ! {
+        // Tell the listener threads to stop
+        self.running.store(false, Ordering::SeqCst);
+
+        // Bring back clients we minimized so nothing is left hidden
+        if self.restore_minimized.load(Ordering::SeqCst) {
+            let windows = self.state.lock().unwrap().get_windows().to_vec();
+            for window in &windows {
+                let _ = self.wm.restore_window(&window.id);
             }
         }
+
+        let _ = runtime::remove_owned(&runtime::socket_path());
+        let _ = runtime::remove_owned(&runtime::pid_file());
+
+        println!("Daemon stopped");
+        std::process::exit(0);
     }
 }
 
@@ -46,11 +83,28 @@ pub struct Daemon {
     state: Arc<Mutex<CycleState>>,
     config: Config,
     character_order: Option<Vec<String>>,
+    groups: BTreeMap<String, Vec<String>>,
+    events: EventBus,
+    shutdown: Shutdown,
+    /// Set while the backend pushes focus changes, making per-command syncs unnecessary
+    tracking: Arc<AtomicBool>,
+    /// The overlay's window, if any, and when it was looked up
+    overlay: Option<(Instant, Option<WindowHandle>)>,
+    /// Where reloaded bindings go, once input is being read
+    input: Option<InputHandle>,
+}
+
+/// The input threads' ends that take new bindings and devices
+struct InputHandle {
+    engine: Sender<Input>,
+    watcher: Sender<Wanted>,
 }
 
 impl Daemon {
     pub fn new(wm: Arc<dyn WindowManager>, config: Config) -> Self {
-        let state = Arc::new(Mutex::new(CycleState::new()));
+        let events = EventBus::new();
+        let state = Arc::new(Mutex::new(CycleState::with_events(events.clone())));
+        state.lock().unwrap().set_rules(config.cycle_rules());
 
         // Initialize windows
         if let Ok(windows) = wm.get_eve_windows() {
@@ -63,55 +117,146 @@ impl Daemon {
             println!("Loaded character order from characters.txt");
         }
 
+        let groups = Config::load_groups();
+        if !groups.is_empty() {
+            let names: Vec<&str> = groups.keys().map(String::as_str).collect();
+            println!("Loaded cycle groups: {}", names.join(", "));
+        }
+
+        let shutdown = Shutdown {
+            wm: Arc::clone(&wm),
+            state: Arc::clone(&state),
+            restore_minimized: Arc::new(AtomicBool::new(
+                config.minimize_inactive && wm.capabilities().minimize,
+            )),
+            running: Arc::new(AtomicBool::new(true)),
+        };
+
         Self {
             wm,
             state,
             config,
             character_order,
+            groups,
+            events,
+            shutdown,
+            tracking: Arc::new(AtomicBool::new(false)),
+            overlay: None,
+            input: None,
         }
     }
 
-    pub fn run(&mut self) -> Result<()> {
+    pub fn run(mut self) -> Result<()> {
+        runtime::ensure_runtime_dir()?;
+        let socket_path = runtime::socket_path();
+
+        if UnixStream::connect(&socket_path).is_ok() {
+            anyhow::bail!("Daemon already running on {}", socket_path.display());
+        }
+
         // Remove old socket if it exists
-        let _ = fs::remove_file(SOCKET_PATH);
+        let _ = runtime::remove_owned(&socket_path);
 
-        let listener = UnixListener::bind(SOCKET_PATH)?;
-        println!("EVE Multibox daemon listening on {}", SOCKET_PATH);
+        let listener = UnixListener::bind(&socket_path)?;
+        println!("EVE Multibox daemon listening on {}", socket_path.display());
 
-        // Start mouse event listener if enabled
-        if self.config.enable_mouse_buttons {
-            let mouse_listener = MouseListener::new(self.config.clone());
-            let wm_clone = Arc::clone(&self.wm);
-            let state_clone = Arc::clone(&self.state);
+        fs::write(runtime::pid_file(), std::process::id().to_string())?;
 
-            match mouse_listener.spawn(wm_clone, state_clone) {
-                Ok(_) => println!("Mouse button listener started"),
-                Err(e) => {
-                    eprintln!("Warning: Could not start mouse listener: {}", e);
-                    eprintln!(
-                        "Mouse buttons will not work. You can disable this warning by setting"
-                    );
-                    eprintln!("'enable_mouse_buttons = false' in ~/.config/nicotine/config.toml");
-                }
+        // Shut down cleanly on SIGTERM/SIGINT/SIGHUP (see block_shutdown_signals)
+        let shutdown = self.shutdown.clone();
+        std::thread::spawn(move || {
+            if let Ok(signal) = shutdown_signals().wait() {
+                println!("Received {}, shutting down", signal);
+                shutdown.run();
             }
-        }
+        });
+
+        self.warn_unsupported();
+
+        // Follow window changes as they happen if the backend can watch them,
+        // otherwise refresh the window list periodically in background
+        let changes = match self.wm.watch_windows() {
+            Ok(changes) => changes,
+            Err(e) => {
+                eprintln!("Warning: Could not watch windows, polling instead: {}", e);
+                None
+            }
+        };
+        self.tracking.store(changes.is_some(), Ordering::SeqCst);
 
-        // Refresh window list periodically in background
         let wm_clone = Arc::clone(&self.wm);
         let state_clone = Arc::clone(&self.state);
-        std::thread::spawn(move || loop {
-            std::thread::sleep(std::time::Duration::from_millis(500));
-            if let Ok(windows) = wm_clone.get_eve_windows() {
-                state_clone.lock().unwrap().update_windows(windows);
+        let tracking = Arc::clone(&self.tracking);
+        std::thread::spawn(move || {
+            if let Some(changes) = changes {
+                println!("Watching window changes");
+                for change in changes {
+                    match change {
+                        WindowEvent::WindowsChanged => {
+                            if let Ok(windows) = wm_clone.get_eve_windows() {
+                                state_clone.lock().unwrap().update_windows(windows);
+                            }
+                        }
+                        WindowEvent::ActiveChanged(window_id) => {
+                            state_clone.lock().unwrap().sync_with_active(&window_id);
+                        }
+                    }
+                }
+
+                tracking.store(false, Ordering::SeqCst);
+                eprintln!("Window watcher stopped, falling back to polling");
+            }
+
+            loop {
+                std::thread::sleep(std::time::Duration::from_millis(500));
+                if let Ok(windows) = wm_clone.get_eve_windows() {
+                    state_clone.lock().unwrap().update_windows(windows);
+                }
             }
         });
 
+        // Connections are read on their own threads, but every command runs
+        // on a single worker so they are applied in the order they arrived
+        let (jobs, queue) = mpsc::channel();
+        let connection = Connection {
+            jobs,
+            events: self.events.clone(),
+            shutdown: self.shutdown.clone(),
+        };
+
+        // Show live client thumbnails if enabled
+        if self.config.show_previews && self.wm.capabilities().thumbnails {
+            let dispatch = connection.clone();
+            match Previews::spawn(
+                Arc::clone(&self.state),
+                self.events.subscribe(),
+                self.config.clone(),
+                Arc::clone(&self.shutdown.running),
+                move |command| match dispatch.submit(command) {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(error)) => {
+                        eprintln!("Failed to switch to previewed client: {}", error.message)
+                    }
+                    Err(e) => eprintln!("Failed to switch to previewed client: {}", e),
+                },
+            ) {
+                Ok(_) => println!("Client previews started"),
+                Err(e) => eprintln!("Warning: Could not start client previews: {}", e),
+            }
+        }
+
+        self.input = connection.listen_for_input(&self.config, &self.groups);
+        self.spawn_worker(queue);
+
         for stream in listener.incoming() {
             match stream {
                 Ok(stream) => {
-                    if let Err(e) = self.handle_client(stream) {
-                        eprintln!("Error handling client: {}", e);
-                    }
+                    let connection = connection.clone();
+                    std::thread::spawn(move || {
+                        if let Err(e) = connection.handle(stream) {
+                            eprintln!("Error handling client: {}", e);
+                        }
+                    });
                 }
                 Err(e) => {
                     eprintln!("Connection error: {}", e);
@@ -122,55 +267,433 @@ impl Daemon {
         Ok(())
     }
 
-    fn handle_client(&mut self, stream: UnixStream) -> Result<()> {
-        let mut reader = BufReader::new(&stream);
-        let mut line = String::new();
-        reader.read_line(&mut line)?;
-
-        if let Some(command) = Command::from_str(&line) {
-            match command {
-                Command::Forward => {
-                    let mut state = self.state.lock().unwrap();
+    fn spawn_worker(mut self, queue: Receiver<Job>) {
+        std::thread::spawn(move || {
+            let mut pending: Option<Job> = None;
 
-                    // Sync with active window first
-                    if let Ok(active) = self.wm.get_active_window() {
-                        state.sync_with_active(active);
-                    }
+            loop {
+                let job = match pending.take() {
+                    Some(job) => job,
+                    None => match queue.recv() {
+                        Ok(job) => job,
+                        Err(_) => return,
+                    },
+                };
 
-                    state.cycle_forward(&*self.wm, self.config.minimize_inactive)?;
+                // Out of its focus context, a binding does nothing
+                if !self.focus_allows(job.focus) {
+                    let _ = job.reply.send(Ok(None));
+                    continue;
                 }
-                Command::Backward => {
-                    let mut state = self.state.lock().unwrap();
 
-                    // Sync with active window first
-                    if let Ok(active) = self.wm.get_active_window() {
-                        state.sync_with_active(active);
+                let mode = self.config.cycle_mode;
+                let Some((scope, steps)) = job.command.cycle_steps(mode) else {
+                    let outcome = self.run_command(&job.command);
+                    let _ = job.reply.send(outcome);
+                    continue;
+                };
+
+                // Coalesce presses that queued up while the last switch was
+                // in flight, so a burst becomes a single switch
+                let mut steps = steps;
+                let mut replies = vec![job.reply];
+                while let Ok(next) = queue.try_recv() {
+                    match next.command.cycle_steps(self.config.cycle_mode) {
+                        Some((same, more)) if same == scope && next.focus == job.focus => {
+                            steps += more;
+                            replies.push(next.reply);
+                        }
+                        _ => {
+                            pending = Some(next);
+                            break;
+                        }
                     }
+                }
 
-                    state.cycle_backward(&*self.wm, self.config.minimize_inactive)?;
+                let outcome = self.cycle(&scope, steps).map(|_| None).map_err(|e| {
+                    eprintln!("Cycling {} steps failed: {}", steps, e);
+                    ErrorBody::from_error(&e)
+                });
+                for reply in replies {
+                    let _ = reply.send(outcome.clone());
                 }
-                Command::Switch(target) => {
-                    let mut state = self.state.lock().unwrap();
+            }
+        });
+    }
 
-                    // Sync with active window first
-                    if let Ok(active) = self.wm.get_active_window() {
-                        state.sync_with_active(active);
-                    }
+    /// Make sure the current index matches the focused window. Not needed
+    /// while the backend reports focus changes as they happen.
+    fn sync_active(&self, state: &mut CycleState) {
+        if self.tracking.load(Ordering::SeqCst) {
+            return;
+        }
 
-                    state.switch_to(
-                        target,
-                        &*self.wm,
-                        self.config.minimize_inactive,
-                        self.character_order.as_deref(),
-                    )?;
-                }
-                Command::Refresh => {
-                    let windows = self.wm.get_eve_windows()?;
-                    self.state.lock().unwrap().update_windows(windows);
+        if let Ok(active) = self.wm.get_active_window() {
+            state.sync_with_active(&active);
+        }
+    }
+
+    /// Whether focus is where `focus` wants it: an EVE client or the overlay
+    /// for `eve_focused`. Backends that can't tell act regardless.
+    fn focus_allows(&mut self, focus: FocusRule) -> bool {
+        if focus == FocusRule::Always {
+            return true;
+        }
+        let Ok(active) = self.wm.get_active_window() else {
+            return true;
+        };
+
+        let on_client = self
+            .state
+            .lock()
+            .unwrap()
+            .get_windows()
+            .iter()
+            .any(|window| window.id == active);
+        focus.allows(
+            on_client
+                || self
+                    .overlay_window()
+                    .is_some_and(|overlay| overlay == active),
+        )
+    }
+
+    /// The overlay's window, looked up again once the last lookup is stale
+    fn overlay_window(&mut self) -> Option<WindowHandle> {
+        if let Some((found_at, overlay)) = &self.overlay {
+            if found_at.elapsed() < OVERLAY_LOOKUP_TTL {
+                return overlay.clone();
+            }
+        }
+
+        let overlay = self.wm.find_window_by_title("Nicotine").ok().flatten();
+        self.overlay = Some((Instant::now(), overlay.clone()));
+        overlay
+    }
+
+    /// Hand the reloaded bindings and the devices they need to the input threads
+    fn rebind(&self) {
+        let Some(input) = &self.input else {
+            return;
+        };
+
+        let bindings = bindings::from_config(&self.config, &self.groups);
+        let _ = input.watcher.send(wanted_devices(&self.config, &bindings));
+        let _ = input.engine.send(Input::Rebind {
+            bindings,
+            wheel: self.config.wheel_settings(),
+        });
+    }
+
+    fn run_command(&mut self, command: &Command) -> Outcome {
+        self.execute(command).map_err(|e| {
+            eprintln!("Command {:?} failed: {}", command, e);
+            ErrorBody::from_error(&e)
+        })
+    }
+
+    /// Tell the user about configured features this backend can't provide
+    fn warn_unsupported(&self) {
+        let capabilities = self.wm.capabilities();
+        let wanted = [
+            (
+                self.config.minimize_inactive && !capabilities.minimize,
+                "minimize_inactive",
+            ),
+            (
+                self.config.show_previews && !capabilities.thumbnails,
+                "Client previews (show_previews)",
+            ),
+        ];
+
+        for (_, feature) in wanted.iter().filter(|(missing, _)| *missing) {
+            eprintln!("Warning: {}, ignoring it", self.wm.unsupported(feature));
+        }
+    }
+
+    fn group(&self, name: &str) -> Result<&[String]> {
+        self.groups
+            .get(name)
+            .map(Vec::as_slice)
+            .ok_or_else(|| SwitchError::GroupNotFound(name.to_string()).into())
+    }
+
+    fn cycle(&mut self, scope: &CycleScope, steps: isize) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        self.sync_active(&mut state);
+
+        let minimize_inactive = self.config.minimize_inactive;
+        match scope {
+            CycleScope::All(CycleMode::Order) => {
+                state.cycle_by(steps, &*self.wm, minimize_inactive)
+            }
+            CycleScope::All(CycleMode::Mru) => state.cycle_recent(
+                steps,
+                &*self.wm,
+                minimize_inactive,
+                self.config.mru_timeout(),
+            ),
+            CycleScope::Group(name) => {
+                state.cycle_group(steps, self.group(name)?, &*self.wm, minimize_inactive)
+            }
+        }
+    }
+
+    fn execute(&mut self, command: &Command) -> Result<Option<Value>> {
+        match command {
+            Command::Forward { .. } | Command::Backward { .. } | Command::Last => {
+                if let Some((scope, steps)) = command.cycle_steps(self.config.cycle_mode) {
+                    self.cycle(&scope, steps)?;
                 }
-                Command::Quit => {
-                    std::process::exit(0);
+            }
+            Command::Switch { target, group } => {
+                let order = match group {
+                    Some(name) => Some(self.group(name)?),
+                    None => self.character_order.as_deref(),
+                };
+
+                let mut state = self.state.lock().unwrap();
+                self.sync_active(&mut state);
+
+                state.switch_to(*target, &*self.wm, self.config.minimize_inactive, order)?;
+            }
+            Command::Focus { character } => {
+                let mut state = self.state.lock().unwrap();
+                self.sync_active(&mut state);
+
+                state.switch_to_character(character, &*self.wm, self.config.minimize_inactive)?;
+            }
+            Command::Refresh => {
+                let windows = self.wm.get_eve_windows()?;
+                self.state.lock().unwrap().update_windows(windows);
+            }
+            Command::Stack => {
+                if !self.wm.capabilities().stacking {
+                    anyhow::bail!(self.wm.unsupported("Window stacking"));
                 }
+                let windows = self.state.lock().unwrap().get_windows().to_vec();
+                self.wm.stack_windows(&windows, &self.config)?;
+                self.events.publish(Event::Stacked {
+                    count: windows.len(),
+                });
+            }
+            Command::Reload => {
+                self.config = Config::load()?;
+                self.character_order = Config::load_characters();
+                self.groups = Config::load_groups();
+                self.state
+                    .lock()
+                    .unwrap()
+                    .set_rules(self.config.cycle_rules());
+                self.shutdown.restore_minimized.store(
+                    self.config.minimize_inactive && self.wm.capabilities().minimize,
+                    Ordering::SeqCst,
+                );
+                self.warn_unsupported();
+                self.rebind();
+                self.events.publish(Event::ConfigReloaded);
+            }
+            Command::ToggleOverlay => {
+                self.events.publish(Event::OverlayToggled);
+            }
+            Command::Status => {
+                return Ok(Some(serde_json::to_value(self.status())?));
+            }
+            Command::List => {
+                let state = self.state.lock().unwrap();
+                return Ok(Some(serde_json::to_value(self.clients(&state))?));
+            }
+            Command::Subscribe => {
+                // The connection streams events itself, the worker only
+                // provides the snapshot
+                return Ok(Some(serde_json::to_value(self.status())?));
+            }
+            Command::Quit => {
+                // The connection shuts down once the reply has been written
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn status(&self) -> StatusInfo {
+        let state = self.state.lock().unwrap();
+        StatusInfo {
+            daemon_version: env!("CARGO_PKG_VERSION").to_string(),
+            pid: std::process::id(),
+            current_index: state.get_current_index(),
+            clients: self.clients(&state),
+        }
+    }
+
+    fn clients(&self, state: &CycleState) -> Vec<ClientInfo> {
+        let current_index = state.get_current_index();
+
+        state
+            .get_windows()
+            .iter()
+            .enumerate()
+            .map(|(index, window)| ClientInfo {
+                index,
+                id: window.id.clone(),
+                title: window.title.clone(),
+                active: index == current_index,
+                slot: self
+                    .character_order
+                    .as_ref()
+                    .and_then(|order| order.iter().position(|name| *name == window.title))
+                    .map(|position| position + 1),
+            })
+            .collect()
+    }
+}
+
+/// The devices to read for `bindings`
+fn wanted_devices(config: &Config, bindings: &HashMap<Chord, Binding>) -> Wanted {
+    let mouse = config
+        .enable_mouse_buttons
+        .then(|| MouseListener::new(config));
+    // Keyboards are only read when there's more than the mouse buttons
+    let keyboards = (!config.bindings.is_empty()).then(|| {
+        let wheels = bindings
+            .keys()
+            .any(|chord| matches!(chord.trigger, Trigger::Wheel(_)));
+        KeyboardListener::new(bindings.keys().flat_map(Chord::keys).collect(), wheels)
+    });
+    Wanted { mouse, keyboards }
+}
+
+/// Result of a command as sent back to the connection that issued it
+type Outcome = std::result::Result<Option<Value>, ErrorBody>;
+
+struct Job {
+    command: Command,
+    /// Where focus must be for the command to run
+    focus: FocusRule,
+    reply: Sender<Outcome>,
+}
+
+/// Per-connection handle onto the daemon
+#[derive(Clone)]
+struct Connection {
+    jobs: Sender<Job>,
+    events: EventBus,
+    shutdown: Shutdown,
+}
+
+impl Connection {
+    fn handle(&self, stream: UnixStream) -> Result<()> {
+        // Don't let a client that never finishes its line hold a thread forever
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+        let mut reader = BufReader::new(&stream);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+
+        let request = match Request::parse(&line) {
+            Ok(request) => request,
+            Err(error) => {
+                let _ = write_response(&stream, &Response::failure(None, error));
+                return Ok(());
+            }
+        };
+
+        if request.command == Command::Subscribe {
+            return self.stream_events(stream, request.id);
+        }
+
+        let response = match self.submit(request.command.clone())? {
+            Ok(data) => Response::success(request.id, data),
+            Err(error) => Response::failure(request.id, error),
+        };
+
+        // Legacy clients close the socket without reading the reply
+        let _ = write_response(&stream, &response);
+
+        if request.command == Command::Quit {
+            self.shutdown.run();
+        }
+
+        Ok(())
+    }
+
+    /// Read the mouse and keyboards for bindings. What they trigger runs on
+    /// the worker like socket commands, so it's ordered and coalesced the same way.
+    /// Start reading input devices for the bindings, returning where
+    /// reloads send new ones
+    fn listen_for_input(
+        &self,
+        config: &Config,
+        groups: &BTreeMap<String, Vec<String>>,
+    ) -> Option<InputHandle> {
+        let bindings = bindings::from_config(config, groups);
+        let running = &self.shutdown.running;
+        let (inputs, input_queue) = mpsc::channel();
+        let (watcher, wanted_queue) = mpsc::channel();
+
+        let wanted = wanted_devices(config, &bindings);
+        match InputWatcher::new(wanted).spawn(inputs.clone(), wanted_queue, Arc::clone(running)) {
+            Ok(_) => println!("Input listener started"),
+            Err(e) => {
+                eprintln!("Warning: Could not start input listener: {}", e);
+                eprintln!("Mouse buttons and key bindings will not work");
+                return None;
+            }
+        }
+
+        let connection = self.clone();
+        BindingEngine::new(bindings, config.wheel_settings()).spawn(
+            input_queue,
+            Arc::clone(running),
+            move |binding| match connection.submit_binding(binding) {
+                Ok(Ok(_)) => {}
+                Ok(Err(error)) => eprintln!("Binding failed: {}", error.message),
+                Err(e) => eprintln!("Binding failed: {}", e),
+            },
+        );
+
+        Some(InputHandle {
+            engine: inputs,
+            watcher,
+        })
+    }
+
+    fn submit(&self, command: Command) -> Result<Outcome> {
+        self.submit_binding(Binding {
+            command,
+            focus: FocusRule::Always,
+        })
+    }
+
+    fn submit_binding(&self, binding: Binding) -> Result<Outcome> {
+        let (reply, outcome) = mpsc::channel();
+        self.jobs
+            .send(Job {
+                command: binding.command,
+                focus: binding.focus,
+                reply,
+            })
+            .context("Daemon worker has stopped")?;
+        outcome.recv().context("Daemon worker has stopped")
+    }
+
+    /// Reply with a status snapshot, then stream events until the
+    /// subscriber disconnects
+    fn stream_events(&self, mut stream: UnixStream, id: Option<u64>) -> Result<()> {
+        // Subscribe before taking the snapshot so no event falls in between
+        let events = self.events.subscribe();
+        let response = match self.submit(Command::Subscribe)? {
+            Ok(snapshot) => Response::success(id, snapshot),
+            Err(error) => Response::failure(id, error),
+        };
+        write_response(&stream, &response)?;
+        stream.set_read_timeout(None)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+        for event in events {
+            let line = serde_json::to_string(&event)?;
+            if writeln!(stream, "{}", line).is_err() || stream.flush().is_err() {
+                break;
             }
         }
 
@@ -178,13 +701,394 @@ impl Daemon {
     }
 }
 
-pub fn send_command(command: &str) -> Result<()> {
-    if !Path::new(SOCKET_PATH).exists() {
-        anyhow::bail!("Daemon not running. Start with: eve-multibox daemon");
+fn write_response(mut stream: &UnixStream, response: &Response) -> Result<()> {
+    let line = serde_json::to_string(response)?;
+    writeln!(stream, "{}", line)?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn connect() -> Result<UnixStream> {
+    let socket_path = runtime::socket_path();
+    if !socket_path.exists() {
+        anyhow::bail!("Daemon not running. Start with: nicotine start");
     }
 
-    let mut stream = UnixStream::connect(SOCKET_PATH)?;
-    writeln!(stream, "{}", command)?;
+    Ok(UnixStream::connect(socket_path)?)
+}
+
+fn send_request(stream: &mut UnixStream, command: Command) -> Result<()> {
+    let line = serde_json::to_string(&Request::new(command))?;
+    writeln!(stream, "{}", line)?;
     stream.flush()?;
     Ok(())
 }
+
+fn read_response(reader: &mut impl BufRead) -> Result<Response> {
+    let mut reply = String::new();
+    if reader
+        .read_line(&mut reply)
+        .context("Daemon did not reply")?
+        == 0
+    {
+        anyhow::bail!("Daemon closed the connection without replying");
+    }
+
+    serde_json::from_str(&reply).context("Failed to parse daemon reply")
+}
+
+/// Send a command to the running daemon and wait for its reply.
+/// Errors mean the daemon could not be reached; command failures are
+/// reported through the returned `Response`.
+pub fn send_command(command: Command) -> Result<Response> {
+    exchange(connect()?, command)
+}
+
+/// Like `send_command`, but `None` when no daemon is listening, so the
+/// caller can do the work itself. Once the daemon has the command, not
+/// hearing back is an error: it may have run it already.
+pub fn try_send_command(command: Command) -> Result<Option<Response>> {
+    let stream = match UnixStream::connect(runtime::socket_path()) {
+        Ok(stream) => stream,
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => {
+            return Ok(None)
+        }
+        Err(e) => return Err(e).context("Failed to reach the daemon"),
+    };
+
+    exchange(stream, command).map(Some)
+}
+
+fn exchange(mut stream: UnixStream, command: Command) -> Result<Response> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    send_request(&mut stream, command)?;
+
+    read_response(&mut BufReader::new(&stream))
+}
+
+/// Live event stream from the daemon, ends when the daemon goes away
+pub struct Subscription {
+    reader: BufReader<UnixStream>,
+}
+
+impl Iterator for Subscription {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(serde_json::from_str(&line).context("Failed to parse daemon event")),
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+/// Subscribe to daemon events. Returns the daemon state at the time of
+/// subscribing followed by every change from then on.
+pub fn subscribe() -> Result<(StatusInfo, Subscription)> {
+    let mut stream = connect()?;
+    send_request(&mut stream, Command::Subscribe)?;
+
+    let mut reader = BufReader::new(stream);
+    let snapshot = read_response(&mut reader)?
+        .into_result()?
+        .context("Daemon returned no snapshot")?;
+
+    Ok((serde_json::from_value(snapshot)?, Subscription { reader }))
+}
+
+/// Stop the daemon for this user and display. Asks it to quit over the socket
+/// (falling back to SIGTERM), and only kills the recorded PID if it doesn't
+/// exit within `timeout`. Returns false if no daemon was running.
+pub fn stop(timeout: Duration) -> Result<bool> {
+    let pid = fs::read_to_string(runtime::pid_file())
+        .ok()
+        .and_then(|contents| contents.trim().parse::<i32>().ok())
+        .map(Pid::from_raw)
+        .filter(|&pid| is_own_daemon(pid));
+
+    let asked = send_command(Command::Quit).is_ok();
+    if !asked {
+        match pid {
+            Some(pid) => signal::kill(pid, Signal::SIGTERM)?,
+            None => return Ok(false),
+        }
+    }
+
+    let deadline = Instant::now() + timeout;
+    let exited = |pid: Option<Pid>| match pid {
+        Some(pid) => signal::kill(pid, None) == Err(Errno::ESRCH),
+        None => !runtime::socket_path().exists(),
+    };
+
+    while !exited(pid) {
+        if Instant::now() >= deadline {
+            let Some(pid) = pid else {
+                anyhow::bail!("Daemon did not exit within {:?}", timeout);
+            };
+            eprintln!(
+                "Daemon did not exit within {:?}, killing pid {}",
+                timeout, pid
+            );
+            signal::kill(pid, Signal::SIGKILL)?;
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    // Clean up whatever a killed daemon left behind
+    let _ = runtime::remove_owned(&runtime::socket_path());
+    let _ = runtime::remove_owned(&runtime::pid_file());
+
+    Ok(true)
+}
+
+/// Guard against stale pidfiles: the PID must be a nicotine process owned by us
+fn is_own_daemon(pid: Pid) -> bool {
+    let proc_dir = format!("/proc/{}", pid);
+
+    let owned = fs::metadata(&proc_dir)
+        .map(|metadata| metadata.uid() == nix::unistd::getuid().as_raw())
+        .unwrap_or(false);
+    let is_nicotine = fs::read_to_string(format!("{}/comm", proc_dir))
+        .map(|comm| comm.trim() == "nicotine")
+        .unwrap_or(false);
+
+    owned && is_nicotine
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use crate::protocol::ErrorCode;
+    use crate::window_manager::{Capabilities, EveWindow};
+
+    struct MockWindowManager {
+        activated_windows: Mutex<Vec<WindowHandle>>,
+        active: Mutex<Option<WindowHandle>>,
+        title_lookups: std::sync::atomic::AtomicUsize,
+    }
+
+    impl WindowManager for MockWindowManager {
+        fn name(&self) -> &'static str {
+            "Mock"
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                move_windows: false,
+                resize_windows: false,
+                minimize: false,
+                stacking: false,
+                events: false,
+                thumbnails: false,
+                overlay_dragging: false,
+            }
+        }
+
+        fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
+            Ok(vec![
+                EveWindow {
+                    id: 100u32.into(),
+                    title: "Alpha".to_string(),
+                },
+                EveWindow {
+                    id: 200u32.into(),
+                    title: "Beta".to_string(),
+                },
+            ])
+        }
+
+        fn activate_window(&self, window_id: &WindowHandle) -> Result<()> {
+            self.activated_windows
+                .lock()
+                .unwrap()
+                .push(window_id.clone());
+            Ok(())
+        }
+
+        fn stack_windows(&self, _windows: &[EveWindow], _config: &Config) -> Result<()> {
+            Ok(())
+        }
+
+        fn get_active_window(&self) -> Result<WindowHandle> {
+            self.active
+                .lock()
+                .unwrap()
+                .clone()
+                .context("no active window")
+        }
+
+        fn find_window_by_title(&self, _title: &str) -> Result<Option<WindowHandle>> {
+            self.title_lookups.fetch_add(1, Ordering::SeqCst);
+            Ok(None)
+        }
+
+        fn minimize_window(&self, _window_id: &WindowHandle) -> Result<()> {
+            Ok(())
+        }
+
+        fn restore_window(&self, _window_id: &WindowHandle) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn test_connection() -> (Connection, Arc<MockWindowManager>) {
+        test_connection_with_groups(BTreeMap::new())
+    }
+
+    fn test_connection_with_groups(
+        groups: BTreeMap<String, Vec<String>>,
+    ) -> (Connection, Arc<MockWindowManager>) {
+        let config = test_config("");
+
+        let wm = Arc::new(MockWindowManager {
+            activated_windows: Mutex::new(Vec::new()),
+            active: Mutex::new(None),
+            title_lookups: Default::default(),
+        });
+        let mut daemon = Daemon::new(wm.clone(), config);
+        daemon.groups = groups;
+        let events = daemon.events.clone();
+        let shutdown = daemon.shutdown.clone();
+
+        let (jobs, queue) = mpsc::channel();
+        daemon.spawn_worker(queue);
+        let connection = Connection {
+            jobs,
+            events,
+            shutdown,
+        };
+        (connection, wm)
+    }
+
+    fn request(connection: &Connection, line: &str) -> Response {
+        let (client, server) = UnixStream::pair().unwrap();
+        writeln!(&client, "{}", line).unwrap();
+        connection.handle(server).unwrap();
+        read_response(&mut BufReader::new(&client)).unwrap()
+    }
+
+    #[test]
+    fn test_legacy_command_gets_reply() {
+        let (connection, wm) = test_connection();
+
+        let response = request(&connection, "forward");
+        assert!(response.ok);
+        assert_eq!(
+            *wm.activated_windows.lock().unwrap(),
+            vec![WindowHandle::from(200u32)]
+        );
+    }
+
+    #[test]
+    fn test_focus_rule_drops_presses_outside_eve() {
+        let (connection, wm) = test_connection();
+        *wm.active.lock().unwrap() = Some(WindowHandle::from(999u32));
+
+        let press = || Binding {
+            command: Command::Forward { group: None },
+            focus: FocusRule::EveFocused,
+        };
+        for _ in 0..3 {
+            assert_eq!(connection.submit_binding(press()).unwrap().unwrap(), None);
+        }
+        assert!(wm.activated_windows.lock().unwrap().is_empty());
+        // The overlay isn't looked up again for every press
+        assert_eq!(wm.title_lookups.load(Ordering::SeqCst), 1);
+
+        // On a client the press goes through
+        *wm.active.lock().unwrap() = Some(WindowHandle::from(100u32));
+        connection.submit_binding(press()).unwrap().unwrap();
+        assert_eq!(
+            *wm.activated_windows.lock().unwrap(),
+            vec![WindowHandle::from(200u32)]
+        );
+    }
+
+    #[test]
+    fn test_switch_error_is_structured() {
+        let (connection, _wm) = test_connection();
+
+        let response = request(&connection, r#"{"id":3,"cmd":"switch","target":5}"#);
+        assert!(!response.ok);
+        assert_eq!(response.id, Some(3));
+        assert_eq!(response.error.unwrap().code, ErrorCode::OutOfRange);
+    }
+
+    #[test]
+    fn test_unsupported_stack_is_an_error() {
+        let (connection, _wm) = test_connection();
+
+        let response = request(&connection, r#"{"cmd":"stack"}"#);
+        assert!(!response.ok);
+        let error = response.error.unwrap();
+        assert_eq!(error.code, ErrorCode::CommandFailed);
+        assert_eq!(
+            error.message,
+            "Window stacking is not available with the Mock backend"
+        );
+    }
+
+    #[test]
+    fn test_group_commands() {
+        let groups = BTreeMap::from([(
+            "dps".to_string(),
+            vec!["Beta".to_string(), "Alpha".to_string()],
+        )]);
+        let (connection, wm) = test_connection_with_groups(groups);
+
+        assert!(request(&connection, "switch:dps:1").ok);
+        assert!(request(&connection, r#"{"cmd":"forward","group":"dps"}"#).ok);
+        assert_eq!(
+            *wm.activated_windows.lock().unwrap(),
+            vec![WindowHandle::from(200u32), WindowHandle::from(100u32)]
+        );
+
+        let response = request(&connection, "backward:logi");
+        assert_eq!(response.error.unwrap().code, ErrorCode::GroupNotFound);
+    }
+
+    #[test]
+    fn test_list_returns_clients() {
+        let (connection, _wm) = test_connection();
+
+        let response = request(&connection, r#"{"cmd":"list"}"#);
+        let clients: Vec<ClientInfo> = serde_json::from_value(response.data.unwrap()).unwrap();
+        assert_eq!(clients.len(), 2);
+        assert!(clients[0].active);
+        assert_eq!(clients[1].title, "Beta");
+    }
+
+    #[test]
+    fn test_subscribe_streams_events() {
+        let (connection, _wm) = test_connection();
+        let (client, server) = UnixStream::pair().unwrap();
+        writeln!(&client, "subscribe").unwrap();
+
+        let subscriber = connection.clone();
+        std::thread::spawn(move || subscriber.handle(server));
+
+        let mut reader = BufReader::new(client);
+        let snapshot: StatusInfo =
+            serde_json::from_value(read_response(&mut reader).unwrap().data.unwrap()).unwrap();
+        assert_eq!(snapshot.clients.len(), 2);
+
+        connection
+            .submit(Command::Forward { group: None })
+            .unwrap()
+            .unwrap();
+
+        let mut subscription = Subscription { reader };
+        assert_eq!(
+            subscription.next().unwrap().unwrap(),
+            Event::ActiveChanged {
+                index: 1,
+                id: 200u32.into(),
+                title: "Beta".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,335 @@
+use crate::config::Config;
+use crate::kwin_scripting::KWIN_SERVICE;
+use crate::runtime;
+use crate::window_manager::{
+    detect_backend, detect_display_server, detect_wayland_compositor, Backend, DisplayServer,
+};
+use anyhow::Result;
+use evdev::{Device, Key};
+use std::env;
+use std::ffi::OsStr;
+use std::io::ErrorKind;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+/// External tools some backends shell out to, with what needs them
+const TOOLS: &[(&str, &str)] = &[
+    ("wmctrl", "KWin wmctrl fallback, overlay restacking"),
+    ("xdotool", "KWin wmctrl fallback"),
+    ("kdotool", "KWin wmctrl fallback, native Wayland windows"),
+    ("xrandr", "display size and monitor detection"),
+];
+
+/// `nicotine doctor`: explain what nicotine sees and why a backend might not work
+pub fn run() -> Result<()> {
+    println!("🚬 Nicotine doctor");
+
+    let config = match Config::load() {
+        Ok(config) => Some(config),
+        Err(e) => {
+            println!("✗ config.toml: {:#}", e);
+            None
+        }
+    };
+
+    session(config.as_ref());
+    tools();
+    input_devices(config.as_ref());
+    sockets();
+    self_test(config.as_ref());
+
+    Ok(())
+}
+
+fn session(config: Option<&Config>) {
+    println!();
+    println!("Session");
+
+    for var in [
+        "XDG_SESSION_TYPE",
+        "XDG_CURRENT_DESKTOP",
+        "WAYLAND_DISPLAY",
+        "DISPLAY",
+        "SWAYSOCK",
+        "HYPRLAND_INSTANCE_SIGNATURE",
+    ] {
+        let value = env::var(var).unwrap_or_else(|_| "(unset)".to_string());
+        println!("  {:<28} {}", var, value);
+    }
+
+    let detected = match detect_display_server() {
+        DisplayServer::X11 => "X11".to_string(),
+        DisplayServer::Wayland => format!("Wayland, {:?} compositor", detect_wayland_compositor()),
+    };
+    println!("  {:<28} {}", "Detected", detected);
+
+    let configured = config.map(|config| config.backend).unwrap_or_default();
+    let resolved = match configured {
+        Backend::Auto => detect_backend()
+            .map(|backend| format!("auto → {}", backend))
+            .unwrap_or_else(|e| format!("auto → {}", e)),
+        backend => format!("{} (from config.toml)", backend),
+    };
+    println!("  {:<28} {}", "Backend", resolved);
+}
+
+fn tools() {
+    println!();
+    println!("Tools");
+
+    let path = env::var_os("PATH").unwrap_or_default();
+    for (tool, used_for) in TOOLS {
+        match find_in_path(tool, &path) {
+            Some(found) => println!("  ✓ {:<10} {}", tool, found.display()),
+            None => println!("  ✗ {:<10} not found ({})", tool, used_for),
+        }
+    }
+}
+
+fn input_devices(config: Option<&Config>) {
+    println!();
+    println!("Input devices");
+
+    let entries = match std::fs::read_dir("/dev/input") {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("  ✗ /dev/input: {}", e);
+            return;
+        }
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(OsStr::to_str)
+                .is_some_and(|name| name.starts_with("event"))
+        })
+        .collect();
+    paths.sort();
+
+    let mut denied = 0;
+    let mut with_side_buttons = 0;
+    for path in &paths {
+        match Device::open(path) {
+            Ok(device) => {
+                let side_buttons = device.supported_keys().is_some_and(|keys| {
+                    keys.contains(Key::BTN_SIDE) || keys.contains(Key::BTN_EXTRA)
+                });
+                if side_buttons {
+                    with_side_buttons += 1;
+                    println!(
+                        "  ✓ {} {} (side buttons)",
+                        path.display(),
+                        device.name().unwrap_or("Unknown")
+                    );
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => denied += 1,
+            Err(_) => {}
+        }
+    }
+
+    if denied > 0 {
+        println!(
+            "  ✗ {} of {} devices not readable. Add yourself to the input group: \
+             sudo usermod -aG input $USER (then log out and back in)",
+            denied,
+            paths.len()
+        );
+    }
+    if with_side_buttons == 0 {
+        println!("  ✗ No readable mouse with side buttons, mouse bindings won't work");
+    }
+
+    if let Some(path) = config.and_then(|config| config.mouse_device_path.as_deref()) {
+        match Device::open(path) {
+            Ok(_) => println!("  ✓ mouse_device_path {} is readable", path),
+            Err(e) => println!("  ✗ mouse_device_path {}: {}", path, e),
+        }
+    }
+}
+
+fn sockets() {
+    println!();
+    println!("Sockets");
+
+    println!(
+        "  {:<10} {}",
+        "Daemon",
+        socket_state(&runtime::socket_path())
+    );
+
+    if let Some(socket) = wayland_socket() {
+        println!("  {:<10} {}", "Wayland", socket_state(&socket));
+    }
+
+    if let Some(socket) = env::var("DISPLAY").ok().and_then(|d| x11_socket(&d)) {
+        println!("  {:<10} {}", "X11", socket_state(&socket));
+    }
+
+    if let Some(socket) = env::var_os("SWAYSOCK") {
+        println!("  {:<10} {}", "Sway", socket_state(Path::new(&socket)));
+    }
+
+    if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        match crate::hyprland_ipc::socket_dir() {
+            Ok(dir) => println!(
+                "  {:<10} {}",
+                "Hyprland",
+                socket_state(&dir.join(".socket.sock"))
+            ),
+            Err(e) => println!("  {:<10} ✗ {}", "Hyprland", e),
+        }
+    }
+
+    println!("  {:<10} {}", "KWin", kwin_state());
+}
+
+/// Connect to the configured backend and list EVE clients, like the daemon would
+fn self_test(config: Option<&Config>) {
+    println!();
+    println!("Self-test");
+
+    let Some(config) = config else {
+        println!("  ✗ Skipped, config.toml didn't load");
+        return;
+    };
+
+    let wm = match crate::create_window_manager(config) {
+        Ok(wm) => wm,
+        Err(e) => {
+            println!("  ✗ Backend failed: {:#}", e);
+            return;
+        }
+    };
+
+    println!("  ✓ Connected to the {} backend", wm.name());
+
+    let capabilities = wm.capabilities();
+    let missing: Vec<&str> = [
+        ("moving windows", capabilities.move_windows),
+        ("resizing windows", capabilities.resize_windows),
+        ("minimizing", capabilities.minimize),
+        ("stacking", capabilities.stacking),
+        ("window events", capabilities.events),
+        ("client previews", capabilities.thumbnails),
+        ("overlay dragging", capabilities.overlay_dragging),
+    ]
+    .into_iter()
+    .filter(|(_, supported)| !supported)
+    .map(|(feature, _)| feature)
+    .collect();
+    if !missing.is_empty() {
+        println!("  ! Not supported by this backend: {}", missing.join(", "));
+    }
+
+    match wm.get_eve_windows() {
+        Ok(windows) if windows.is_empty() => {
+            println!("  ✗ No EVE clients found. Are any running and logged in?")
+        }
+        Ok(windows) => {
+            println!("  ✓ Found {} EVE clients", windows.len());
+            for window in windows {
+                println!("    {:<14}  {}", window.id, window.title);
+            }
+        }
+        Err(e) => println!("  ✗ Listing windows failed: {:#}", e),
+    }
+
+    match wm.get_active_window() {
+        Ok(active) => println!("  ✓ Active window {}", active),
+        Err(e) => println!("  ✗ Active window: {:#}", e),
+    }
+}
+
+/// Look `tool` up like a shell would: the first executable file in `path`
+fn find_in_path(tool: &str, path: &OsStr) -> Option<PathBuf> {
+    env::split_paths(path)
+        .map(|dir| dir.join(tool))
+        .find(|candidate| {
+            candidate.metadata().is_ok_and(|metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            })
+        })
+}
+
+/// The local socket for an X11 `DISPLAY` like `:0` or `:1.0`.
+/// Remote displays (`host:0`) have none.
+fn x11_socket(display: &str) -> Option<PathBuf> {
+    let number = display.strip_prefix(':')?;
+    let number = number.split('.').next()?;
+    number.parse::<u32>().ok()?;
+    Some(PathBuf::from(format!("/tmp/.X11-unix/X{}", number)))
+}
+
+/// `$WAYLAND_DISPLAY`, relative to `$XDG_RUNTIME_DIR` unless absolute
+fn wayland_socket() -> Option<PathBuf> {
+    let display = PathBuf::from(env::var_os("WAYLAND_DISPLAY")?);
+    if display.is_absolute() {
+        return Some(display);
+    }
+    Some(PathBuf::from(env::var_os("XDG_RUNTIME_DIR")?).join(display))
+}
+
+fn socket_state(path: &Path) -> String {
+    match UnixStream::connect(path) {
+        Ok(_) => format!("✓ {} accepting connections", path.display()),
+        Err(e) if e.kind() == ErrorKind::NotFound => format!("✗ {} missing", path.display()),
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+            format!("✗ {} stale (nothing listening)", path.display())
+        }
+        Err(e) => format!("✗ {} {}", path.display(), e),
+    }
+}
+
+fn kwin_state() -> String {
+    let owner = zbus::blocking::Connection::session().and_then(|conn| {
+        conn.call_method(
+            Some("org.freedesktop.DBus"),
+            "/org/freedesktop/DBus",
+            Some("org.freedesktop.DBus"),
+            "GetNameOwner",
+            &KWIN_SERVICE,
+        )
+    });
+
+    match owner {
+        Ok(_) => format!("✓ {} on the session bus", KWIN_SERVICE),
+        Err(zbus::Error::MethodError(..)) => format!("✗ {} not on the session bus", KWIN_SERVICE),
+        Err(e) => format!("✗ Session bus: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_x11_socket() {
+        assert_eq!(x11_socket(":0"), Some(PathBuf::from("/tmp/.X11-unix/X0")));
+        assert_eq!(x11_socket(":1.0"), Some(PathBuf::from("/tmp/.X11-unix/X1")));
+        assert_eq!(x11_socket("localhost:10.0"), None);
+        assert_eq!(x11_socket(":abc"), None);
+    }
+
+    #[test]
+    fn test_find_in_path_needs_executable() {
+        let dir = env::temp_dir().join(format!("nicotine-doctor-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let tool = dir.join("wmctrl");
+        std::fs::write(&tool, "#!/bin/sh\n").unwrap();
+        let path = env::join_paths([Path::new("/nonexistent"), &dir]).unwrap();
+
+        std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert_eq!(find_in_path("wmctrl", &path), None);
+
+        std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(find_in_path("wmctrl", &path), Some(tool));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
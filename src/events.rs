@@ -0,0 +1,116 @@
+use crate::window_manager::WindowHandle;
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+
+/// Events streamed to `subscribe` clients, one JSON line each
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    ActiveChanged {
+        index: usize,
+        id: WindowHandle,
+        title: String,
+    },
+    ClientAdded {
+        id: WindowHandle,
+        title: String,
+    },
+    ClientRemoved {
+        id: WindowHandle,
+        title: String,
+    },
+    Stacked {
+        count: usize,
+    },
+    ConfigReloaded,
+    /// Someone asked overlays to hide or show themselves
+    OverlayToggled,
+}
+
+/// Events a subscriber may fall behind by before it's dropped
+const QUEUE_LIMIT: usize = 1024;
+
+/// Fan-out of daemon events to any number of subscribers.
+/// Subscribers that have gone away, or stopped reading and let their queue
+/// fill up, are dropped on the next publish.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<SyncSender<Event>>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (tx, rx) = mpsc::sync_channel(QUEUE_LIMIT);
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    pub fn publish(&self, event: Event) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.try_send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_reaches_all_subscribers() {
+        let bus = EventBus::new();
+        let first = bus.subscribe();
+        let second = bus.subscribe();
+
+        bus.publish(Event::Stacked { count: 3 });
+
+        assert_eq!(first.try_recv().unwrap(), Event::Stacked { count: 3 });
+        assert_eq!(second.try_recv().unwrap(), Event::Stacked { count: 3 });
+    }
+
+    #[test]
+    fn test_dropped_subscribers_are_removed() {
+        let bus = EventBus::new();
+        let kept = bus.subscribe();
+        drop(bus.subscribe());
+
+        bus.publish(Event::ConfigReloaded);
+
+        assert_eq!(bus.subscribers.lock().unwrap().len(), 1);
+        assert_eq!(kept.try_recv().unwrap(), Event::ConfigReloaded);
+    }
+
+    #[test]
+    fn test_stalled_subscribers_are_removed() {
+        let bus = EventBus::new();
+        let stalled = bus.subscribe();
+
+        for _ in 0..=QUEUE_LIMIT {
+            bus.publish(Event::ConfigReloaded);
+        }
+
+        assert!(bus.subscribers.lock().unwrap().is_empty());
+        // What was queued can still be read, then the stream ends
+        assert_eq!(stalled.iter().count(), QUEUE_LIMIT);
+    }
+
+    #[test]
+    fn test_event_serialization() {
+        let event = Event::ActiveChanged {
+            index: 1,
+            id: 0x55d1_c2a8_e0f0u64.into(),
+            title: "Alpha".to_string(),
+        };
+        let line = serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            line,
+            r#"{"event":"active_changed","index":1,"id":"0x55d1c2a8e0f0","title":"Alpha"}"#
+        );
+    }
+}
@@ -0,0 +1,252 @@
+use crate::window_manager::WindowHandle;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+/// `$XDG_RUNTIME_DIR/hypr/$HYPRLAND_INSTANCE_SIGNATURE`, or `/tmp/hypr/...`
+/// for Hyprland versions that predate the runtime dir
+pub fn socket_dir() -> Result<PathBuf> {
+    let signature = std::env::var("HYPRLAND_INSTANCE_SIGNATURE")
+        .context("HYPRLAND_INSTANCE_SIGNATURE not set. Make sure you're running Hyprland")?;
+
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| PathBuf::from(dir).join("hypr").join(&signature));
+    let legacy_dir = PathBuf::from("/tmp/hypr").join(&signature);
+
+    runtime_dir
+        .into_iter()
+        .chain([legacy_dir])
+        .find(|dir| dir.join(".socket.sock").exists())
+        .context("Hyprland socket not found. Make sure you're running Hyprland")
+}
+
+/// Send one request on `.socket.sock`. Hyprland answers and closes the
+/// connection, so every request gets a fresh one.
+pub fn request(dir: &Path, command: &str) -> Result<String> {
+    let mut stream = UnixStream::connect(dir.join(".socket.sock"))
+        .context("Failed to connect to Hyprland socket")?;
+
+    stream.write_all(command.as_bytes())?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+/// Send a `j/` (JSON) query such as `clients` or `activewindow`
+pub fn query(dir: &Path, command: &str) -> Result<Value> {
+    let reply = request(dir, &format!("j/{}", command))?;
+    serde_json::from_str(&reply).with_context(|| format!("Failed to parse Hyprland {}", command))
+}
+
+/// Run any number of dispatches in one round trip
+pub fn dispatch(dir: &Path, dispatches: &[String]) -> Result<()> {
+    if dispatches.is_empty() {
+        return Ok(());
+    }
+
+    let reply = request(dir, &batch(dispatches))?;
+
+    let failures: Vec<&str> = reply
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|result| !result.is_empty() && *result != "ok")
+        .collect();
+
+    if !failures.is_empty() {
+        anyhow::bail!("Hyprland dispatch failed: {}", failures.join("; "));
+    }
+
+    Ok(())
+}
+
+fn batch(dispatches: &[String]) -> String {
+    let commands: Vec<String> = dispatches
+        .iter()
+        .map(|dispatch| format!("dispatch {}", dispatch))
+        .collect();
+    format!("[[BATCH]]{}", commands.join(";"))
+}
+
+/// Convert a Hyprland window address (`0x55d1c2a8e0f0`, or `55d1c2a8e0f0` as
+/// events send it) to a handle. Its `Display` gives back the `0x` form
+/// `address:` selectors expect.
+pub fn address_to_handle(address: &str) -> WindowHandle {
+    let hex = address.strip_prefix("0x").unwrap_or(address);
+    u64::from_str_radix(hex, 16)
+        .map(WindowHandle::Id)
+        .unwrap_or_else(|_| WindowHandle::Name(address.to_string()))
+}
+
+/// Split an event line like `openwindow>>ADDRESS,WORKSPACE,CLASS,TITLE`
+pub fn parse_event(line: &str) -> Option<(&str, &str)> {
+    line.split_once(">>")
+}
+
+/// Line-based event stream from `.socket2.sock`, as `(event, data)` pairs
+pub struct HyprlandEvents {
+    reader: BufReader<UnixStream>,
+}
+
+impl HyprlandEvents {
+    pub fn connect(dir: &Path) -> Result<Self> {
+        let stream = UnixStream::connect(dir.join(".socket2.sock"))
+            .context("Failed to connect to Hyprland event socket")?;
+        Ok(Self {
+            reader: BufReader::new(stream),
+        })
+    }
+}
+
+impl Iterator for HyprlandEvents {
+    type Item = Result<(String, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    if let Some((event, data)) = parse_event(line.trim_end()) {
+                        return Some(Ok((event.to_string(), data.to_string())));
+                    }
+                }
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("nicotine-hypr-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A fake `.socket.sock` answering one request, returning what it received
+    fn fake_hyprland(dir: &Path, reply: &'static str) -> std::thread::JoinHandle<String> {
+        let listener = UnixListener::bind(dir.join(".socket.sock")).unwrap();
+
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0u8; 4096];
+            let read = stream.read(&mut buffer).unwrap();
+            stream.write_all(reply.as_bytes()).unwrap();
+            String::from_utf8_lossy(&buffer[..read]).to_string()
+        })
+    }
+
+    #[test]
+    fn test_batch_format() {
+        let dispatches = vec![
+            "togglefloating address:0x1".to_string(),
+            "movewindowpixel exact 0 0,address:0x1".to_string(),
+        ];
+        assert_eq!(
+            batch(&dispatches),
+            "[[BATCH]]dispatch togglefloating address:0x1;dispatch movewindowpixel exact 0 0,address:0x1"
+        );
+    }
+
+    #[test]
+    fn test_dispatch_sends_one_batch() {
+        let dir = test_dir("dispatch");
+        let server = fake_hyprland(&dir, "ok\n\nok");
+
+        dispatch(
+            &dir,
+            &[
+                "focuswindow address:0x1".to_string(),
+                "focuswindow address:0x2".to_string(),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            server.join().unwrap(),
+            "[[BATCH]]dispatch focuswindow address:0x1;dispatch focuswindow address:0x2"
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_dispatch_reports_failures() {
+        let dir = test_dir("failure");
+        let _server = fake_hyprland(&dir, "ok\n\nNo such window found");
+
+        let error = dispatch(
+            &dir,
+            &["a".to_string(), "focuswindow address:0x9".to_string()],
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("No such window found"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_query_parses_json() {
+        let dir = test_dir("query");
+        let server = fake_hyprland(&dir, r#"[{"address":"0x1a2b","title":"EVE - Alpha"}]"#);
+
+        let clients = query(&dir, "clients").unwrap();
+        assert_eq!(clients[0]["title"], "EVE - Alpha");
+        assert_eq!(server.join().unwrap(), "j/clients");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_event_stream() {
+        let dir = test_dir("events");
+        let listener = UnixListener::bind(dir.join(".socket2.sock")).unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .write_all(b"activewindowv2>>1a2b\nopenwindow>>3c4d,1,steam_app_8500,EVE - Beta\n")
+                .unwrap();
+        });
+
+        let events: Vec<_> = HyprlandEvents::connect(&dir)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(
+            events,
+            vec![
+                ("activewindowv2".to_string(), "1a2b".to_string()),
+                (
+                    "openwindow".to_string(),
+                    "3c4d,1,steam_app_8500,EVE - Beta".to_string()
+                ),
+            ]
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_address_to_handle() {
+        assert_eq!(address_to_handle("0x1a2b"), WindowHandle::Id(0x1a2b));
+        assert_eq!(address_to_handle("1a2b"), WindowHandle::Id(0x1a2b));
+        assert_eq!(
+            address_to_handle("garbage"),
+            WindowHandle::Name("garbage".to_string())
+        );
+    }
+
+    #[test]
+    fn test_wide_address_survives_round_trip() {
+        let handle = address_to_handle("0x55d1c2a8e0f0");
+        assert_eq!(handle.to_string(), "0x55d1c2a8e0f0");
+        assert_eq!(address_to_handle("55d1c2a8e0f0"), handle);
+    }
+}
@@ -0,0 +1,296 @@
+use crate::bindings::{Input, Wheel};
+use crate::keyboard_listener::KeyboardListener;
+use crate::mouse_listener::MouseListener;
+use anyhow::{Context, Result};
+use evdev::{Device, InputEventKind};
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use std::io::ErrorKind;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+
+/// Where the device nodes live
+const INPUT_DIR: &str = "/dev/input";
+
+/// How often the loop checks `running` when no input arrives
+const POLL_TIMEOUT_MS: u16 = 500;
+
+/// What a device was opened as, for messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Mouse,
+    Keyboard,
+}
+
+struct Watched {
+    id: usize,
+    kind: Kind,
+    name: String,
+    path: PathBuf,
+    device: Device,
+}
+
+/// Which devices to read, replaced when the config is reloaded
+pub struct Wanted {
+    pub mouse: Option<MouseListener>,
+    pub keyboards: Option<KeyboardListener>,
+}
+
+/// Reads every matching mouse and keyboard from one poll loop, and attaches
+/// devices that show up later in `/dev/input`: a re-plugged USB mouse or a
+/// wireless one waking up
+pub struct InputWatcher {
+    mouse: Option<MouseListener>,
+    keyboards: Option<KeyboardListener>,
+    devices: Vec<Watched>,
+    next_id: usize,
+}
+
+fn is_event_device(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("event"))
+}
+
+impl InputWatcher {
+    pub fn new(wanted: Wanted) -> Self {
+        Self {
+            mouse: wanted.mouse,
+            keyboards: wanted.keyboards,
+            devices: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    fn kind_of(&self, path: &Path, device: &Device) -> Option<Kind> {
+        if self
+            .mouse
+            .as_ref()
+            .is_some_and(|mouse| mouse.matches(path, device))
+        {
+            Some(Kind::Mouse)
+        } else if self
+            .keyboards
+            .as_ref()
+            .is_some_and(|keyboards| keyboards.matches(device))
+        {
+            Some(Kind::Keyboard)
+        } else {
+            None
+        }
+    }
+
+    /// Start reading the device at `path` if it's one we want and not read yet
+    fn attach(&mut self, path: &Path) {
+        if self.devices.iter().any(|watched| watched.path == path) {
+            return;
+        }
+
+        // Fails until udev has given us access to a new node, which is
+        // retried on its attribute change
+        let Ok(device) = Device::open(path) else {
+            return;
+        };
+        let Some(kind) = self.kind_of(path, &device) else {
+            return;
+        };
+
+        // Reads must not block the loop
+        if let Err(e) = fcntl(device.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK)) {
+            eprintln!("Failed to set up {}: {}", path.display(), e);
+            return;
+        }
+
+        let name = device.name().unwrap_or("Unknown").to_string();
+        println!("Found {:?} device: {} ({})", kind, name, path.display());
+        self.devices.push(Watched {
+            id: self.next_id,
+            kind,
+            name,
+            path: path.to_path_buf(),
+            device,
+        });
+        self.next_id += 1;
+    }
+
+    /// Attach every wanted device present right now
+    fn scan(&mut self) -> Result<()> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(INPUT_DIR)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| is_event_device(path))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            self.attach(&path);
+        }
+        Ok(())
+    }
+
+    fn has(&self, kind: Kind) -> bool {
+        self.devices.iter().any(|watched| watched.kind == kind)
+    }
+
+    /// Pass on what `devices[index]` has to say. Returns false once it's gone.
+    fn read(&mut self, index: usize, inputs: &Sender<Input>) -> bool {
+        let watched = &mut self.devices[index];
+        match watched.device.fetch_events() {
+            Ok(events) => {
+                for event in events {
+                    let input = match event.kind() {
+                        InputEventKind::Key(key) => Input::Key {
+                            device: watched.id,
+                            key,
+                            value: event.value(),
+                        },
+                        InputEventKind::RelAxis(axis) => {
+                            match Wheel::from_axis(axis, event.value()) {
+                                Some(wheel) => Input::Wheel {
+                                    wheel,
+                                    notches: event.value().unsigned_abs(),
+                                },
+                                None => continue,
+                            }
+                        }
+                        _ => continue,
+                    };
+                    let _ = inputs.send(input);
+                }
+                true
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => true,
+            Err(e) => {
+                println!(
+                    "{:?} device {} disconnected ({}), waiting for it to come back",
+                    watched.kind, watched.name, e
+                );
+                false
+            }
+        }
+    }
+
+    /// Read what's wanted now: let go of devices that no longer match and
+    /// attach the ones that do
+    fn rematch(&mut self, wanted: Wanted, inputs: &Sender<Input>) {
+        self.mouse = wanted.mouse;
+        self.keyboards = wanted.keyboards;
+
+        let mut index = 0;
+        while index < self.devices.len() {
+            let watched = &self.devices[index];
+            if self.kind_of(&watched.path, &watched.device).is_some() {
+                index += 1;
+            } else {
+                let gone = self.devices.remove(index);
+                println!("No longer reading {}", gone.name);
+                let _ = inputs.send(Input::Gone(gone.id));
+            }
+        }
+
+        if let Err(e) = self.scan() {
+            eprintln!("Failed to look for input devices: {}", e);
+        }
+    }
+
+    /// Attach the devices present now, then read them and watch for new ones
+    /// in a background thread until `running` is cleared. What's `wanted`
+    /// can be changed through `rematch`.
+    pub fn spawn(
+        mut self,
+        inputs: Sender<Input>,
+        rematch: Receiver<Wanted>,
+        running: Arc<AtomicBool>,
+    ) -> Result<std::thread::JoinHandle<()>> {
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?;
+        // Permissions arrive after the node does, hence IN_ATTRIB
+        inotify
+            .add_watch(
+                INPUT_DIR,
+                AddWatchFlags::IN_CREATE | AddWatchFlags::IN_ATTRIB,
+            )
+            .with_context(|| format!("Failed to watch {}", INPUT_DIR))?;
+
+        self.scan()?;
+
+        let mouse_missing = !self.has(Kind::Mouse);
+        if let Some(mouse) = self.mouse.as_mut() {
+            if mouse_missing && mouse.is_configured() {
+                mouse.fall_back_to_auto();
+                self.scan()?;
+            }
+        }
+
+        let hint = "Make sure you have permission to read /dev/input/event*";
+        if self.mouse.is_some() && !self.has(Kind::Mouse) {
+            eprintln!(
+                "Warning: No mouse device with side buttons found yet, waiting for one. {}",
+                hint
+            );
+            eprintln!("You can disable this warning by setting 'enable_mouse_buttons = false' in ~/.config/nicotine/config.toml");
+        }
+        if self.keyboards.is_some() && !self.has(Kind::Keyboard) {
+            eprintln!("Warning: No keyboard found yet, waiting for one. {}", hint);
+        }
+
+        // DON'T grab the devices - we only want to passively listen to events
+        // Grabbing would prevent normal mouse and keyboard usage!
+        let handle = std::thread::spawn(move || {
+            while running.load(Ordering::SeqCst) {
+                if let Some(wanted) = rematch.try_iter().last() {
+                    self.rematch(wanted, &inputs);
+                }
+
+                let ready: Vec<bool> = {
+                    let mut fds = vec![PollFd::new(inotify.as_fd(), PollFlags::POLLIN)];
+                    for watched in &self.devices {
+                        // SAFETY: the devices outlive `fds`, which is dropped
+                        // before any device is added or removed
+                        let fd = unsafe { BorrowedFd::borrow_raw(watched.device.as_raw_fd()) };
+                        fds.push(PollFd::new(fd, PollFlags::POLLIN));
+                    }
+
+                    match poll(&mut fds, POLL_TIMEOUT_MS) {
+                        Ok(0) | Err(Errno::EINTR) => continue,
+                        Ok(_) => {}
+                        Err(e) => {
+                            eprintln!("Input devices can't be polled: {}", e);
+                            break;
+                        }
+                    }
+
+                    fds.iter()
+                        .map(|fd| fd.revents().is_some_and(|events| !events.is_empty()))
+                        .collect()
+                };
+
+                // Back to front, so removing one keeps the others' indices
+                for index in (0..ready.len() - 1).rev() {
+                    if ready[index + 1] && !self.read(index, &inputs) {
+                        let gone = self.devices.remove(index);
+                        let _ = inputs.send(Input::Gone(gone.id));
+                    }
+                }
+
+                if ready[0] {
+                    for event in inotify.read_events().unwrap_or_default() {
+                        if let Some(name) = event.name {
+                            let path = Path::new(INPUT_DIR).join(name);
+                            if is_event_device(&path) {
+                                self.attach(&path);
+                            }
+                        }
+                    }
+                }
+            }
+
+            println!("Input listener stopped");
+        });
+
+        Ok(handle)
+    }
+}
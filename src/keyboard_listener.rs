@@ -0,0 +1,30 @@
+use crate::bindings;
+use evdev::{Device, Key, RelativeAxisType};
+use std::collections::HashSet;
+
+/// Picks the keyboards, keypads and other devices with bound keys that the
+/// binding engine reads
+pub struct KeyboardListener {
+    keys: HashSet<Key>,
+    wheels: bool,
+}
+
+impl KeyboardListener {
+    /// Listen for `keys` and modifiers, and for wheels if `wheels` are bound
+    pub fn new(keys: HashSet<Key>, wheels: bool) -> Self {
+        Self { keys, wheels }
+    }
+
+    /// Whether `device` has a bound key, a modifier or a bound wheel
+    pub fn matches(&self, device: &Device) -> bool {
+        let has_key = device.supported_keys().is_some_and(|keys| {
+            keys.iter()
+                .any(|key| bindings::is_modifier(key) || self.keys.contains(&key))
+        });
+        let has_wheel = device.supported_relative_axes().is_some_and(|axes| {
+            axes.contains(RelativeAxisType::REL_WHEEL)
+                || axes.contains(RelativeAxisType::REL_HWHEEL)
+        });
+        has_key || (self.wheels && has_wheel)
+    }
+}
@@ -0,0 +1,631 @@
+use crate::window_manager::{WindowEvent, WindowHandle};
+use anyhow::{Context, Result};
+use async_io::Timer;
+use futures_lite::FutureExt;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use zbus::blocking::{connection, Connection};
+
+pub const KWIN_SERVICE: &str = "org.kde.KWin";
+const SCRIPTING_PATH: &str = "/Scripting";
+const SCRIPTING_INTERFACE: &str = "org.kde.kwin.Scripting";
+const SCRIPT_INTERFACE: &str = "org.kde.kwin.Script";
+
+/// Where scripts call back to on nicotine's own connection
+const BRIDGE_PATH: &str = "/Nicotine";
+const BRIDGE_INTERFACE: &str = "org.nicotine.KWin";
+
+/// Plugin name of the long-running script that reports window changes
+const WATCH_SCRIPT: &str = "nicotine-watch";
+
+/// How long to wait for a script to call back
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long the watch script's `Next` call waits for a request before
+/// returning empty, well inside D-Bus's default 25s call timeout
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Helpers every script starts with. KWin 6 calls clients "windows"; the
+/// `clientList`/`activeClient` fallbacks keep Plasma 5 working.
+const PRELUDE: &str = r#"
+function reply(payload) {
+    callDBus(SERVICE, BRIDGE_PATH, BRIDGE_INTERFACE, "Reply", REQUEST, JSON.stringify(payload));
+}
+function notify(method, argument) {
+    callDBus(SERVICE, BRIDGE_PATH, BRIDGE_INTERFACE, method, argument);
+}
+function windows() {
+    return workspace.windowList ? workspace.windowList() : workspace.clientList();
+}
+function windowId(w) {
+    return String(w.internalId);
+}
+function findWindow(id) {
+    return windows().find(w => windowId(w) === id);
+}
+function activeWindow() {
+    return workspace.windowList ? workspace.activeWindow : workspace.activeClient;
+}
+function activate(w) {
+    if (workspace.windowList) {
+        workspace.activeWindow = w;
+    } else {
+        workspace.activeClient = w;
+    }
+}
+"#;
+
+/// Reports window changes while the watch script is loaded
+const WATCH_BODY: &str = r#"
+const isEve = w => w.caption.startsWith("EVE");
+function track(w) {
+    w.captionChanged.connect(() => { if (isEve(w)) notify("Changed", ""); });
+}
+(workspace.windowActivated || workspace.clientActivated).connect(w => {
+    if (w) notify("Focus", windowId(w));
+});
+(workspace.windowAdded || workspace.clientAdded).connect(w => {
+    track(w);
+    if (isEve(w)) notify("Changed", "");
+});
+(workspace.windowRemoved || workspace.clientRemoved).connect(w => {
+    if (isEve(w)) notify("Changed", "");
+});
+windows().forEach(track);
+
+// Serve requests: each one is a script body run with its own `reply`
+function serve() {
+    callDBus(SERVICE, BRIDGE_PATH, BRIDGE_INTERFACE, "Next", request => {
+        serve();
+        if (!request) return;
+        const { id, body } = JSON.parse(request);
+        new Function("reply", body)(payload => {
+            callDBus(SERVICE, BRIDGE_PATH, BRIDGE_INTERFACE, "Reply", id, JSON.stringify(payload));
+        });
+    });
+}
+serve();
+"#;
+
+type EventSender = Arc<Mutex<Option<Sender<WindowEvent>>>>;
+
+/// Receives calls from KWin scripts on nicotine's connection
+struct Bridge {
+    replies: Mutex<Sender<(String, String)>>,
+    requests: async_channel::Receiver<String>,
+    events: EventSender,
+}
+
+impl Bridge {
+    fn send_event(&self, event: WindowEvent) {
+        let mut events = self.events.lock().unwrap();
+        if let Some(sender) = events.as_ref() {
+            if sender.send(event).is_err() {
+                *events = None; // Nobody is listening anymore
+            }
+        }
+    }
+}
+
+#[zbus::interface(name = "org.nicotine.KWin")]
+impl Bridge {
+    /// Hand the watch script its next request, or "" if none came in time
+    async fn next(&self) -> String {
+        let request = async { self.requests.recv().await.unwrap_or_default() };
+        let idle = async {
+            Timer::after(POLL_INTERVAL).await;
+            String::new()
+        };
+        request.or(idle).await
+    }
+
+    fn reply(&self, request: String, payload: String) {
+        let _ = self.replies.lock().unwrap().send((request, payload));
+    }
+
+    fn focus(&self, window: String) {
+        self.send_event(WindowEvent::ActiveChanged(WindowHandle::Name(window)));
+    }
+
+    fn changed(&self, _unused: String) {
+        self.send_event(WindowEvent::WindowsChanged);
+    }
+}
+
+/// Runs JavaScript inside KWin through `org.kde.kwin.Scripting`.
+///
+/// KWin scripts can't be called into, so they answer by calling `Reply` on
+/// nicotine's own bus connection. Once the watch script is loaded it polls
+/// `Next` for requests; until then every request is a one-shot script.
+pub struct KWinScripts {
+    conn: Connection,
+    service: String,
+    script_dir: PathBuf,
+    next_request: AtomicU32,
+    /// Held for the whole request so replies arrive in order
+    replies: Mutex<Receiver<(String, String)>>,
+    requests: async_channel::Sender<String>,
+    /// Kept to drop requests the watch script never picked up
+    unserved: async_channel::Receiver<String>,
+    /// Whether the watch script is loaded and serving requests
+    serving: AtomicBool,
+    events: EventSender,
+}
+
+impl KWinScripts {
+    /// Connect to the session bus, checking that KWin's scripting interface is there
+    pub fn connect(script_dir: &Path) -> Result<Self> {
+        Self::with_builder(connection::Builder::session()?, script_dir)
+    }
+
+    fn with_builder(builder: connection::Builder<'_>, script_dir: &Path) -> Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let (requests, unserved) = async_channel::unbounded();
+        let events = EventSender::default();
+        let bridge = Bridge {
+            replies: Mutex::new(tx),
+            requests: unserved.clone(),
+            events: Arc::clone(&events),
+        };
+
+        let conn = builder
+            .serve_at(BRIDGE_PATH, bridge)?
+            .build()
+            .context("Failed to connect to the session bus")?;
+        let service = conn
+            .unique_name()
+            .context("Session bus gave no unique name")?
+            .to_string();
+
+        conn.call_method(
+            Some("org.freedesktop.DBus"),
+            "/org/freedesktop/DBus",
+            Some("org.freedesktop.DBus"),
+            "GetNameOwner",
+            &KWIN_SERVICE,
+        )
+        .context("KWin is not on the session bus")?;
+
+        Ok(Self {
+            conn,
+            service,
+            script_dir: script_dir.to_path_buf(),
+            next_request: AtomicU32::new(1),
+            replies: Mutex::new(rx),
+            requests,
+            unserved,
+            serving: AtomicBool::new(false),
+            events,
+        })
+    }
+
+    /// Run `body` (which must call `reply(...)` once) and return its reply
+    pub fn run(&self, body: &str) -> Result<Value> {
+        let replies = self.replies.lock().unwrap();
+        let request = self.next_request.fetch_add(1, Ordering::SeqCst).to_string();
+
+        let payload = match self.serve(&replies, &request, body) {
+            Some(payload) => payload,
+            None => {
+                let name = format!("nicotine-{}-{}", std::process::id(), request);
+                self.load_and_run(&name, &request, body)?;
+                let payload = Self::wait_for(&replies, &request);
+                let _ = self.unload(&name);
+                payload?
+            }
+        };
+
+        serde_json::from_str(&payload).context("Failed to parse KWin script reply")
+    }
+
+    /// Hand the request to the watch script, or `None` if it isn't serving
+    fn serve(
+        &self,
+        replies: &Receiver<(String, String)>,
+        request: &str,
+        body: &str,
+    ) -> Option<String> {
+        if !self.serving.load(Ordering::SeqCst) {
+            return None;
+        }
+
+        let message = json!({ "id": request, "body": body }).to_string();
+        self.requests.send_blocking(message).ok()?;
+
+        match Self::wait_for(replies, request) {
+            Ok(payload) => Some(payload),
+            Err(_) => {
+                // The script stopped polling, so don't leave the request for
+                // it to run later on top of the one-shot retry
+                eprintln!("KWin watch script stopped answering, loading scripts per request");
+                self.serving.store(false, Ordering::SeqCst);
+                while self.unserved.try_recv().is_ok() {}
+                None
+            }
+        }
+    }
+
+    /// Load the long-running watch script, returning its focus and window changes
+    pub fn watch(&self) -> Result<Receiver<WindowEvent>> {
+        let (tx, rx) = mpsc::channel();
+        self.events.lock().unwrap().replace(tx);
+
+        // Replace one left behind by an earlier run
+        let _ = self.unload(WATCH_SCRIPT);
+        self.load_and_run(WATCH_SCRIPT, "watch", WATCH_BODY)?;
+        self.serving.store(true, Ordering::SeqCst);
+
+        Ok(rx)
+    }
+
+    fn wait_for(replies: &Receiver<(String, String)>, request: &str) -> Result<String> {
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let (id, payload) = replies
+                .recv_timeout(remaining)
+                .context("KWin script did not reply")?;
+            // Skip late replies to requests that already timed out
+            if id == request {
+                return Ok(payload);
+            }
+        }
+    }
+
+    fn load_and_run(&self, name: &str, request: &str, body: &str) -> Result<()> {
+        // KWin reads the file in the background once the script runs, so it
+        // stays around until the script is unloaded
+        fs::create_dir_all(&self.script_dir)?;
+        let path = self.script_path(name);
+        fs::write(&path, script(&self.service, request, body))?;
+
+        let id = match self.load(&path, name) {
+            Ok(id) => id,
+            Err(e) => {
+                let _ = fs::remove_file(&path);
+                return Err(e);
+            }
+        };
+
+        // Plasma 6 exports scripts under /Scripting, Plasma 5 at the root
+        let started = self
+            .start(&format!("{}/Script{}", SCRIPTING_PATH, id))
+            .or_else(|_| self.start(&format!("/{}", id)));
+
+        if let Err(e) = started {
+            let _ = self.unload(name);
+            return Err(e.context("Failed to run KWin script"));
+        }
+
+        Ok(())
+    }
+
+    fn load(&self, path: &Path, name: &str) -> Result<i32> {
+        let reply = self
+            .conn
+            .call_method(
+                Some(KWIN_SERVICE),
+                SCRIPTING_PATH,
+                Some(SCRIPTING_INTERFACE),
+                "loadScript",
+                &(path.to_string_lossy().as_ref(), name),
+            )
+            .context("Failed to load KWin script")?;
+
+        let id: i32 = reply.body().deserialize()?;
+        if id < 0 {
+            anyhow::bail!("KWin refused to load script {}", name);
+        }
+        Ok(id)
+    }
+
+    fn start(&self, path: &str) -> Result<()> {
+        self.conn
+            .call_method(Some(KWIN_SERVICE), path, Some(SCRIPT_INTERFACE), "run", &())?;
+        Ok(())
+    }
+
+    fn script_path(&self, name: &str) -> PathBuf {
+        self.script_dir.join(format!("{}.js", name))
+    }
+
+    fn unload(&self, name: &str) -> Result<bool> {
+        let _ = fs::remove_file(self.script_path(name));
+        let reply = self.conn.call_method(
+            Some(KWIN_SERVICE),
+            SCRIPTING_PATH,
+            Some(SCRIPTING_INTERFACE),
+            "unloadScript",
+            &name,
+        )?;
+        Ok(reply.body().deserialize()?)
+    }
+}
+
+impl Drop for KWinScripts {
+    fn drop(&mut self) {
+        if self.events.lock().unwrap().is_some() {
+            let _ = self.unload(WATCH_SCRIPT);
+        }
+    }
+}
+
+/// The full script for one request: constants, helpers, then `body`
+fn script(service: &str, request: &str, body: &str) -> String {
+    let constants: String = [
+        ("SERVICE", service),
+        ("REQUEST", request),
+        ("BRIDGE_PATH", BRIDGE_PATH),
+        ("BRIDGE_INTERFACE", BRIDGE_INTERFACE),
+    ]
+    .iter()
+    .map(|(name, value)| format!("const {} = {};\n", name, js_string(value)))
+    .collect();
+
+    format!("{}{}\n{}", constants, PRELUDE, body)
+}
+
+/// Quote a value for use in a script (JSON strings are valid JavaScript)
+pub fn js_string(value: &str) -> String {
+    Value::from(value).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+
+    /// A private bus for the test, so it never touches the real session
+    struct TestBus {
+        daemon: Child,
+        address: String,
+        dir: PathBuf,
+    }
+
+    impl TestBus {
+        fn start(name: &str) -> Option<Self> {
+            let dir =
+                std::env::temp_dir().join(format!("nicotine-kwin-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .arg(format!("--address=unix:path={}", dir.join("bus").display()))
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take()?)
+                .read_line(&mut address)
+                .ok()?;
+
+            Some(Self {
+                daemon,
+                address: address.trim().to_string(),
+                dir,
+            })
+        }
+
+        fn connection(&self) -> connection::Builder<'static> {
+            connection::Builder::address(self.address.as_str()).unwrap()
+        }
+    }
+
+    impl Drop for TestBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// Stands in for KWin: "runs" a script by reading back its constants and
+    /// answering with a canned reply
+    struct MockKWin {
+        conn: Mutex<Option<Connection>>,
+        scripts: Mutex<Vec<String>>,
+        reply: &'static str,
+    }
+
+    struct MockScripting(Arc<MockKWin>);
+
+    #[zbus::interface(name = "org.kde.kwin.Scripting")]
+    impl MockScripting {
+        #[zbus(name = "loadScript")]
+        fn load_script(&self, path: String, _name: String) -> i32 {
+            let mut scripts = self.0.scripts.lock().unwrap();
+            scripts.push(fs::read_to_string(path).unwrap());
+            scripts.len() as i32 - 1
+        }
+
+        #[zbus(name = "unloadScript")]
+        fn unload_script(&self, _name: String) -> bool {
+            true
+        }
+    }
+
+    struct MockScript {
+        kwin: Arc<MockKWin>,
+        id: usize,
+    }
+
+    #[zbus::interface(name = "org.kde.kwin.Script")]
+    impl MockScript {
+        #[zbus(name = "run")]
+        fn run(&self) {
+            let source = self.kwin.scripts.lock().unwrap()[self.id].clone();
+            let constant = |name: &str| -> String {
+                let prefix = format!("const {} = ", name);
+                let line = source
+                    .lines()
+                    .find_map(|line| line.strip_prefix(&prefix))
+                    .unwrap();
+                serde_json::from_str(line.trim_end_matches(';')).unwrap()
+            };
+            let (service, request) = (constant("SERVICE"), constant("REQUEST"));
+            let conn = self.kwin.conn.lock().unwrap().clone().unwrap();
+            let reply = self.kwin.reply;
+
+            // Call back from another thread, as this one is serving the call
+            std::thread::spawn(move || {
+                conn.call_method(
+                    Some(service.as_str()),
+                    BRIDGE_PATH,
+                    Some(BRIDGE_INTERFACE),
+                    "Reply",
+                    &(request, reply),
+                )
+                .unwrap();
+            });
+        }
+    }
+
+    fn mock_kwin(bus: &TestBus, reply: &'static str) -> Arc<MockKWin> {
+        let kwin = Arc::new(MockKWin {
+            conn: Mutex::new(None),
+            scripts: Mutex::new(Vec::new()),
+            reply,
+        });
+
+        let mut builder = bus
+            .connection()
+            .name(KWIN_SERVICE)
+            .unwrap()
+            .serve_at(SCRIPTING_PATH, MockScripting(Arc::clone(&kwin)))
+            .unwrap();
+        for id in 0..4 {
+            let script = MockScript {
+                kwin: Arc::clone(&kwin),
+                id,
+            };
+            builder = builder
+                .serve_at(format!("{}/Script{}", SCRIPTING_PATH, id), script)
+                .unwrap();
+        }
+
+        kwin.conn.lock().unwrap().replace(builder.build().unwrap());
+        kwin
+    }
+
+    #[test]
+    fn test_script_constants_are_quoted() {
+        let source = script(":1.42", "7", "reply(1);");
+        assert!(source.starts_with("const SERVICE = \":1.42\";\nconst REQUEST = \"7\";\n"));
+        assert!(source.ends_with("reply(1);"));
+        assert_eq!(js_string("EVE - \"Quoted\""), r#""EVE - \"Quoted\"""#);
+    }
+
+    #[test]
+    fn test_run_round_trip_over_bus() {
+        let Some(bus) = TestBus::start("run") else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+        let kwin = mock_kwin(&bus, r#"[{"id":"{abc}","title":"EVE - Alpha"}]"#);
+
+        let scripts =
+            KWinScripts::with_builder(bus.connection(), &bus.dir.join("scripts")).unwrap();
+        let reply = scripts.run("reply(windows());").unwrap();
+
+        assert_eq!(reply[0]["title"], "EVE - Alpha");
+        let loaded = kwin.scripts.lock().unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert!(loaded[0].ends_with("reply(windows());"));
+    }
+
+    #[test]
+    fn test_watch_reports_focus() {
+        let Some(bus) = TestBus::start("watch") else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+        let kwin = mock_kwin(&bus, "null");
+
+        let scripts =
+            KWinScripts::with_builder(bus.connection(), &bus.dir.join("scripts")).unwrap();
+        let events = scripts.watch().unwrap();
+
+        // What the watch script does when a window gets focus
+        let conn = kwin.conn.lock().unwrap().clone().unwrap();
+        conn.call_method(
+            Some(scripts.service.as_str()),
+            BRIDGE_PATH,
+            Some(BRIDGE_INTERFACE),
+            "Focus",
+            &("{abc}",),
+        )
+        .unwrap();
+
+        assert_eq!(
+            events.recv_timeout(REPLY_TIMEOUT).unwrap(),
+            WindowEvent::ActiveChanged(WindowHandle::Name("{abc}".to_string()))
+        );
+        assert!(kwin.scripts.lock().unwrap()[0].ends_with(WATCH_BODY));
+    }
+
+    #[test]
+    fn test_watch_script_serves_requests() {
+        let Some(bus) = TestBus::start("serve") else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+        let kwin = mock_kwin(&bus, "null");
+
+        let scripts =
+            KWinScripts::with_builder(bus.connection(), &bus.dir.join("scripts")).unwrap();
+        let _events = scripts.watch().unwrap();
+
+        // What the watch script does with the request it's handed
+        let conn = kwin.conn.lock().unwrap().clone().unwrap();
+        let service = scripts.service.clone();
+        let script = std::thread::spawn(move || {
+            let request: String = conn
+                .call_method(
+                    Some(service.as_str()),
+                    BRIDGE_PATH,
+                    Some(BRIDGE_INTERFACE),
+                    "Next",
+                    &(),
+                )
+                .unwrap()
+                .body()
+                .deserialize()
+                .unwrap();
+            let request: Value = serde_json::from_str(&request).unwrap();
+            conn.call_method(
+                Some(service.as_str()),
+                BRIDGE_PATH,
+                Some(BRIDGE_INTERFACE),
+                "Reply",
+                &(request["id"].as_str().unwrap(), "true"),
+            )
+            .unwrap();
+            request["body"].as_str().unwrap().to_string()
+        });
+
+        assert_eq!(scripts.run("reply(true);").unwrap(), Value::Bool(true));
+        assert_eq!(script.join().unwrap(), "reply(true);");
+        // Only the watch script was loaded
+        assert_eq!(kwin.scripts.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_connect_fails_without_kwin() {
+        let Some(bus) = TestBus::start("missing") else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+
+        let result = KWinScripts::with_builder(bus.connection(), &bus.dir);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,396 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{ConnectionExt as _, NotifyMask};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+/// How close (in pixels) an edge has to be before it snaps
+pub const SNAP_DISTANCE: i32 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Monitor {
+    pub name: String,
+    pub rect: Rect,
+}
+
+/// Saved placement of nicotine's floating windows for one monitor setup
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Layout {
+    #[serde(default)]
+    pub overlay: Option<Rect>,
+    /// Per-character windows (previews), keyed by character name so they
+    /// survive client restarts
+    #[serde(default)]
+    pub characters: BTreeMap<String, Rect>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Layouts {
+    #[serde(default)]
+    setups: BTreeMap<String, Layout>,
+}
+
+/// Window layouts in `~/.config/nicotine/layouts.toml`, one per monitor setup.
+/// The file is re-read before every change, as the overlay and the daemon
+/// may both be saving to it.
+pub struct LayoutStore {
+    path: PathBuf,
+}
+
+impl LayoutStore {
+    pub fn new() -> Self {
+        Self {
+            path: Config::config_dir().join("layouts.toml"),
+        }
+    }
+
+    fn read(&self) -> Layouts {
+        fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn load(&self, setup: &str) -> Layout {
+        self.read().setups.remove(setup).unwrap_or_default()
+    }
+
+    pub fn save_overlay(&self, setup: &str, rect: Rect) -> Result<()> {
+        self.update(setup, |layout| layout.overlay = Some(rect))
+    }
+
+    pub fn save_character(&self, setup: &str, name: &str, rect: Rect) -> Result<()> {
+        self.update(setup, |layout| {
+            layout.characters.insert(name.to_string(), rect);
+        })
+    }
+
+    fn update(&self, setup: &str, change: impl FnOnce(&mut Layout)) -> Result<()> {
+        let mut layouts = self.read();
+        change(layouts.setups.entry(setup.to_string()).or_default());
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Write to a temporary file first so a reader never sees half a file
+        let tmp = self.path.with_extension("toml.tmp");
+        fs::write(&tmp, toml::to_string_pretty(&layouts)?)?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to save {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+/// Detect connected monitors using `xrandr --listmonitors`, falling back to a
+/// single monitor of the configured display size
+pub fn detect_monitors(config: &Config) -> Vec<Monitor> {
+    let monitors = std::process::Command::new("xrandr")
+        .arg("--listmonitors")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|stdout| parse_monitors(&stdout))
+        .unwrap_or_default();
+
+    if monitors.is_empty() {
+        vec![Monitor {
+            name: "default".to_string(),
+            rect: Rect::new(0, 0, config.display_width, config.display_height),
+        }]
+    } else {
+        monitors
+    }
+}
+
+/// Report the monitors whenever RandR says the screen changed (docking,
+/// undocking, a monitor turned on), from a background thread so nothing
+/// waits on `xrandr`. `current` is what the caller already has. Nothing is
+/// reported without an X server that supports RandR.
+pub fn watch_monitors(config: Config, mut current: Vec<Monitor>) -> Receiver<Vec<Monitor>> {
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        let Ok((conn, screen_num)) = RustConnection::connect(None) else {
+            return;
+        };
+        let root = conn.setup().roots[screen_num].root;
+        let subscribed = conn
+            .randr_query_version(1, 2)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .and_then(|_| {
+                conn.randr_select_input(root, NotifyMask::SCREEN_CHANGE)
+                    .ok()
+            })
+            .and_then(|_| conn.flush().ok());
+        if subscribed.is_none() {
+            return;
+        }
+
+        while let Ok(event) = conn.wait_for_event() {
+            if !matches!(event, Event::RandrScreenChangeNotify(_)) {
+                continue;
+            }
+
+            let monitors = detect_monitors(&config);
+            if monitors != current {
+                current = monitors.clone();
+                if tx.send(monitors).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    rx
+}
+
+/// Key identifying a monitor arrangement, e.g. `DP-1:2560x1440+0+0,eDP-1:1920x1080+2560+0`
+pub fn setup_id(monitors: &[Monitor]) -> String {
+    let mut parts: Vec<String> = monitors
+        .iter()
+        .map(|m| {
+            format!(
+                "{}:{}x{}+{}+{}",
+                m.name, m.rect.width, m.rect.height, m.rect.x, m.rect.y
+            )
+        })
+        .collect();
+    parts.sort();
+    parts.join(",")
+}
+
+/// Parse lines like ` 0: +*DP-1 2560/597x1440/336+0+0  DP-1`
+fn parse_monitors(output: &str) -> Vec<Monitor> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            fields.next().filter(|index| index.ends_with(':'))?;
+            fields.next()?; // +*NAME
+            let geometry = fields.next()?;
+            let name = fields.next()?;
+
+            let (width, rest) = geometry.split_once('x')?;
+            let width = width.split('/').next()?.parse().ok()?;
+
+            let offset_start = rest.find(['+', '-'])?;
+            let height = rest[..offset_start].split('/').next()?.parse().ok()?;
+            let (x, y) = parse_offsets(&rest[offset_start..])?;
+
+            Some(Monitor {
+                name: name.to_string(),
+                rect: Rect::new(x, y, width, height),
+            })
+        })
+        .collect()
+}
+
+/// Parse `+X+Y` (either sign may be `-`)
+fn parse_offsets(offsets: &str) -> Option<(i32, i32)> {
+    let second = offsets[1..].find(['+', '-'])? + 1;
+    let x = offsets[..second].parse().ok()?;
+    let y = offsets[second..].parse().ok()?;
+    Some((x, y))
+}
+
+/// Move `rect` so that edges within `SNAP_DISTANCE` of another window's or a
+/// screen's edges line up with them
+pub fn snap(rect: Rect, others: &[Rect], screens: &[Rect]) -> Rect {
+    let near = |a_start: i32, a_end: i32, b_start: i32, b_end: i32| {
+        a_start <= b_end + SNAP_DISTANCE && b_start <= a_end + SNAP_DISTANCE
+    };
+    let width = rect.width as i32;
+    let height = rect.height as i32;
+
+    let mut x_candidates = Vec::new();
+    let mut y_candidates = Vec::new();
+
+    // Stay inside screen edges
+    for screen in screens {
+        x_candidates.extend([screen.x, screen.right() - width]);
+        y_candidates.extend([screen.y, screen.bottom() - height]);
+    }
+
+    // Sit next to, or line up with, windows that are alongside
+    for other in others {
+        if near(rect.y, rect.bottom(), other.y, other.bottom()) {
+            x_candidates.extend([
+                other.right(),
+                other.x - width,
+                other.x,
+                other.right() - width,
+            ]);
+        }
+        if near(rect.x, rect.right(), other.x, other.right()) {
+            y_candidates.extend([
+                other.bottom(),
+                other.y - height,
+                other.y,
+                other.bottom() - height,
+            ]);
+        }
+    }
+
+    let closest = |current: i32, candidates: &[i32]| {
+        candidates
+            .iter()
+            .copied()
+            .filter(|candidate| (candidate - current).abs() <= SNAP_DISTANCE)
+            .min_by_key(|candidate| (candidate - current).abs())
+            .unwrap_or(current)
+    };
+
+    Rect {
+        x: closest(rect.x, &x_candidates),
+        y: closest(rect.y, &y_candidates),
+        ..rect
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_monitors() {
+        let output = "Monitors: 2\n \
+                      0: +*DP-1 2560/597x1440/336+0+0  DP-1\n \
+                      1: +eDP-1 1920/344x1080/193+2560+0  eDP-1\n";
+
+        let monitors = parse_monitors(output);
+
+        assert_eq!(
+            monitors,
+            vec![
+                Monitor {
+                    name: "DP-1".to_string(),
+                    rect: Rect::new(0, 0, 2560, 1440),
+                },
+                Monitor {
+                    name: "eDP-1".to_string(),
+                    rect: Rect::new(2560, 0, 1920, 1080),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_monitors_negative_offset() {
+        let monitors = parse_monitors(" 0: +HDMI-1 1920/527x1080/296-1920+0  HDMI-1\n");
+        assert_eq!(monitors[0].rect, Rect::new(-1920, 0, 1920, 1080));
+    }
+
+    #[test]
+    fn test_setup_id_ignores_monitor_order() {
+        let a = Monitor {
+            name: "DP-1".to_string(),
+            rect: Rect::new(0, 0, 2560, 1440),
+        };
+        let b = Monitor {
+            name: "eDP-1".to_string(),
+            rect: Rect::new(2560, 0, 1920, 1080),
+        };
+
+        assert_eq!(
+            setup_id(&[a.clone(), b.clone()]),
+            "DP-1:2560x1440+0+0,eDP-1:1920x1080+2560+0"
+        );
+        assert_eq!(setup_id(&[a.clone(), b.clone()]), setup_id(&[b, a]));
+    }
+
+    #[test]
+    fn test_snap_to_screen_edges() {
+        let screen = Rect::new(0, 0, 1920, 1080);
+
+        let snapped = snap(Rect::new(7, 1080 - 180 - 5, 320, 180), &[], &[screen]);
+        assert_eq!((snapped.x, snapped.y), (0, 900));
+
+        // Too far away to snap
+        let free = snap(Rect::new(50, 50, 320, 180), &[], &[screen]);
+        assert_eq!((free.x, free.y), (50, 50));
+    }
+
+    #[test]
+    fn test_snap_next_to_other_window() {
+        let other = Rect::new(100, 100, 320, 180);
+
+        // Dropped just right of the other one, slightly lower
+        let snapped = snap(Rect::new(425, 106, 320, 180), &[other], &[]);
+        assert_eq!((snapped.x, snapped.y), (420, 100));
+    }
+
+    #[test]
+    fn test_snap_ignores_windows_that_are_not_alongside() {
+        let other = Rect::new(100, 100, 320, 180);
+
+        // Far below the other window, horizontal edges must not pull it over
+        let snapped = snap(Rect::new(425, 800, 320, 180), &[other], &[]);
+        assert_eq!(snapped.x, 425);
+    }
+
+    #[test]
+    fn test_store_round_trip_per_setup() {
+        let path =
+            std::env::temp_dir().join(format!("nicotine-layout-test-{}.toml", std::process::id()));
+        let store = LayoutStore { path: path.clone() };
+
+        store
+            .save_character("docked", "Alt One", Rect::new(10, 20, 320, 180))
+            .unwrap();
+        store
+            .save_overlay("docked", Rect::new(0, 0, 220, 320))
+            .unwrap();
+        store
+            .save_character("laptop", "Alt One", Rect::new(5, 5, 160, 90))
+            .unwrap();
+
+        let docked = store.load("docked");
+        assert_eq!(docked.overlay, Some(Rect::new(0, 0, 220, 320)));
+        assert_eq!(
+            docked.characters.get("Alt One"),
+            Some(&Rect::new(10, 20, 320, 180))
+        );
+        assert_eq!(
+            store.load("laptop").characters.get("Alt One"),
+            Some(&Rect::new(5, 5, 160, 90))
+        );
+        assert_eq!(store.load("unknown"), Layout::default());
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -1,70 +1,103 @@
+mod bindings;
 mod config;
 mod cycle_state;
 mod daemon;
+mod doctor;
+mod events;
+mod hyprland_ipc;
+mod input_watcher;
+mod keyboard_listener;
+mod kwin_scripting;
+mod layout;
 mod mouse_listener;
 mod overlay;
+mod preview;
+mod protocol;
+mod runtime;
+mod sway_ipc;
 mod version_check;
 mod wayland_backends;
 mod window_manager;
+mod wlr_toplevel;
 mod x11_manager;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use config::Config;
-use cycle_state::CycleState;
+use cycle_state::{CycleScope, CycleState, SwitchError};
 use daemon::Daemon;
 use daemonize::Daemonize;
 #[allow(deprecated)]
 use nix::fcntl::{flock, FlockArg};
 use overlay::run_overlay;
+use protocol::{ClientInfo, Command, StatusInfo};
 use std::env;
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
 use std::os::unix::fs::OpenOptionsExt;
 use std::os::unix::io::AsRawFd;
 use std::sync::{Arc, Mutex};
-use wayland_backends::{HyprlandManager, KWinManager, SwayManager};
+use std::time::Duration;
+use wayland_backends::{HyprlandManager, KWinManager, KWinScriptManager, SwayManager, WlrManager};
 use window_manager::{
-    detect_display_server, detect_wayland_compositor, DisplayServer, WaylandCompositor,
+    detect_backend, detect_display_server, detect_wayland_compositor, Backend, DisplayServer,
     WindowManager,
 };
 use x11_manager::X11Manager;
 
-fn create_window_manager() -> Result<Arc<dyn WindowManager>> {
-    let display_server = detect_display_server();
+fn create_window_manager(config: &Config) -> Result<Arc<dyn WindowManager>> {
+    let backend = match config.backend {
+        Backend::Auto => {
+            match detect_display_server() {
+                DisplayServer::X11 => println!("Detected X11 display server"),
+                DisplayServer::Wayland => println!(
+                    "Detected Wayland display server with {:?} compositor",
+                    detect_wayland_compositor()
+                ),
+            }
+            detect_backend()?
+        }
+        backend => {
+            println!("Using configured backend: {}", backend);
+            backend
+        }
+    };
 
-    match display_server {
-        DisplayServer::X11 => {
-            println!("Detected X11 display server");
+    connect_backend(backend)
+}
+
+fn connect_backend(backend: Backend) -> Result<Arc<dyn WindowManager>> {
+    match backend {
+        Backend::Auto => connect_backend(detect_backend()?),
+        Backend::X11 => {
+            println!("Using X11 backend");
             Ok(Arc::new(X11Manager::new()?))
         }
-        DisplayServer::Wayland => {
-            let compositor = detect_wayland_compositor();
-            println!(
-                "Detected Wayland display server with {:?} compositor",
-                compositor
-            );
-
-            match compositor {
-                WaylandCompositor::Kde => {
-                    println!("Using KDE/KWin backend");
-                    Ok(Arc::new(KWinManager::new()?))
-                }
-                WaylandCompositor::Sway => {
-                    println!("Using Sway backend");
-                    Ok(Arc::new(SwayManager::new()?))
-                }
-                WaylandCompositor::Hyprland => {
-                    println!("Using Hyprland backend");
-                    Ok(Arc::new(HyprlandManager::new()?))
-                }
-                WaylandCompositor::Gnome => {
-                    anyhow::bail!("GNOME Shell is not yet supported due to restrictive window management APIs")
-                }
-                WaylandCompositor::Other => {
-                    anyhow::bail!(
-                        "Unknown Wayland compositor. Supported: KDE Plasma, Sway, Hyprland"
-                    )
-                }
+        Backend::Kwin => match KWinScriptManager::new() {
+            Ok(manager) => {
+                println!("Using KDE/KWin backend");
+                Ok(Arc::new(manager))
             }
+            Err(e) => {
+                eprintln!("KWin scripting unavailable ({}), falling back to wmctrl", e);
+                println!("Using KDE/KWin backend (wmctrl)");
+                Ok(Arc::new(KWinManager::new()?))
+            }
+        },
+        Backend::Sway => {
+            println!("Using Sway backend");
+            Ok(Arc::new(SwayManager::new()?))
+        }
+        Backend::Hyprland => {
+            println!("Using Hyprland backend");
+            Ok(Arc::new(HyprlandManager::new()?))
+        }
+        Backend::Wlr => {
+            println!("Using wlr-foreign-toplevel backend");
+            let manager = WlrManager::new().context(
+                "Unknown Wayland compositor without wlr-foreign-toplevel-management. \
+                 Supported: KDE Plasma, Sway, Hyprland, and wlroots-based compositors \
+                 (river, labwc, Wayfire, niri, ...)",
+            )?;
+            Ok(Arc::new(manager))
         }
     }
 }
@@ -73,12 +106,20 @@ fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
     let command = args.get(1).map(|s| s.as_str()).unwrap_or("");
 
+    // Diagnoses broken setups, so it must not need a working backend
+    if command == "doctor" {
+        return doctor::run();
+    }
+
     let config = Config::load()?;
-    let wm = create_window_manager()?;
+    // Commands that only talk to the daemon don't need a backend, which may
+    // not even start
+    let wm = || create_window_manager(&config);
 
     match command {
         "start" => {
             println!("Starting Nicotine 🚬");
+            let wm = wm()?;
 
             // Check for updates (non-blocking, silent on errors)
             if let Ok(Some((new_version, url))) = version_check::check_for_updates() {
@@ -91,11 +132,13 @@ fn main() -> Result<()> {
             match daemonize.start() {
                 Ok(_) => {
                     // We're now in the daemon process
+                    daemon::block_shutdown_signals()?;
+
                     // Start daemon in background thread
                     let wm_daemon = Arc::clone(&wm);
                     let config_daemon = config.clone();
                     let daemon_thread = std::thread::spawn(move || {
-                        let mut daemon = Daemon::new(wm_daemon, config_daemon);
+                        let daemon = Daemon::new(wm_daemon, config_daemon);
                         if let Err(e) = daemon.run() {
                             eprintln!("Daemon error: {}", e);
                         }
@@ -107,6 +150,7 @@ fn main() -> Result<()> {
                     if config.show_overlay {
                         // Run overlay in main thread
                         let state = Arc::new(Mutex::new(CycleState::new()));
+                        state.lock().unwrap().set_rules(config.cycle_rules());
                         if let Ok(windows) = wm.get_eve_windows() {
                             state.lock().unwrap().update_windows(windows);
                         }
@@ -132,13 +176,16 @@ fn main() -> Result<()> {
 
         "daemon" => {
             println!("Starting EVE Multibox daemon...");
-            let mut daemon = Daemon::new(wm, config);
+            daemon::block_shutdown_signals()?;
+            let daemon = Daemon::new(wm()?, config);
             daemon.run()?;
         }
 
         "overlay" => {
             println!("Starting EVE Multibox Overlay...");
+            let wm = wm()?;
             let state = Arc::new(Mutex::new(CycleState::new()));
+            state.lock().unwrap().set_rules(config.cycle_rules());
 
             // Initialize windows
             if let Ok(windows) = wm.get_eve_windows() {
@@ -152,6 +199,18 @@ fn main() -> Result<()> {
         }
 
         "stack" => {
+            // Let the daemon stack so subscribers hear about it
+            if let Some(response) = daemon::try_send_command(Command::Stack)? {
+                response.into_result()?;
+                println!("✓ Stacked windows");
+                return Ok(());
+            }
+            let wm = wm()?;
+
+            if !wm.capabilities().stacking {
+                anyhow::bail!(wm.unsupported("Window stacking"));
+            }
+
             println!("Stacking EVE windows...");
             let windows = wm.get_eve_windows()?;
 
@@ -171,32 +230,21 @@ fn main() -> Result<()> {
 
         "cycle-forward" | "forward" | "f" => {
             // Try daemon first
-            if daemon::send_command("forward").is_ok() {
+            if let Some(response) = daemon::try_send_command(Command::Forward { group: None })? {
+                response.into_result()?;
                 return Ok(());
             }
 
             // Fallback to direct mode
 
             // Try to acquire lock, exit immediately if already running
-            let lock_file = "/tmp/nicotine-cycle.lock";
-            let file = match OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .mode(0o644)
-                .open(lock_file)
-            {
-                Ok(f) => f,
-                Err(_) => return Ok(()), // Can't get lock, skip
-            };
-
-            // Try to lock (non-blocking)
-            #[allow(deprecated)]
-            if flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock).is_err() {
+            let Some(_lock) = try_cycle_lock() else {
                 return Ok(()); // Already running, skip this cycle
-            }
+            };
+            let wm = wm()?;
 
             let mut state = CycleState::new();
+            state.set_rules(config.cycle_rules());
             let windows = wm.get_eve_windows()?;
 
             if windows.is_empty() {
@@ -207,7 +255,7 @@ fn main() -> Result<()> {
 
             // Sync with current active window
             if let Ok(active) = wm.get_active_window() {
-                state.sync_with_active(active);
+                state.sync_with_active(&active);
             }
 
             state.cycle_forward(&*wm, config.minimize_inactive)?;
@@ -217,32 +265,21 @@ fn main() -> Result<()> {
 
         "cycle-backward" | "backward" | "b" => {
             // Try daemon first
-            if daemon::send_command("backward").is_ok() {
+            if let Some(response) = daemon::try_send_command(Command::Backward { group: None })? {
+                response.into_result()?;
                 return Ok(());
             }
 
             // Fallback to direct mode
 
             // Try to acquire lock, exit immediately if already running
-            let lock_file = "/tmp/nicotine-cycle.lock";
-            let file = match OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .mode(0o644)
-                .open(lock_file)
-            {
-                Ok(f) => f,
-                Err(_) => return Ok(()), // Can't get lock, skip
-            };
-
-            // Try to lock (non-blocking)
-            #[allow(deprecated)]
-            if flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock).is_err() {
+            let Some(_lock) = try_cycle_lock() else {
                 return Ok(()); // Already running, skip this cycle
-            }
+            };
+            let wm = wm()?;
 
             let mut state = CycleState::new();
+            state.set_rules(config.cycle_rules());
             let windows = wm.get_eve_windows()?;
 
             if windows.is_empty() {
@@ -253,7 +290,7 @@ fn main() -> Result<()> {
 
             // Sync with current active window
             if let Ok(active) = wm.get_active_window() {
-                state.sync_with_active(active);
+                state.sync_with_active(&active);
             }
 
             state.cycle_backward(&*wm, config.minimize_inactive)?;
@@ -261,61 +298,132 @@ fn main() -> Result<()> {
             // Lock is automatically released when file is dropped
         }
 
+        "last" => {
+            // The focus history lives in the daemon, there is no direct mode
+            daemon::send_command(Command::Last)
+                .context("nicotine last needs the daemon (nicotine start)")?
+                .into_result()?;
+        }
+
+        "toggle_overlay" => {
+            // Overlays follow the daemon's events, there is no direct mode
+            daemon::send_command(Command::ToggleOverlay)
+                .context("nicotine toggle_overlay needs the daemon (nicotine start)")?
+                .into_result()?;
+        }
+
+        "status" => {
+            let data = daemon::send_command(Command::Status)?
+                .into_result()?
+                .context("Daemon returned no status")?;
+
+            if args.iter().any(|arg| arg == "--json") {
+                println!("{}", data);
+                return Ok(());
+            }
+
+            let status: StatusInfo = serde_json::from_value(data)?;
+            println!(
+                "Nicotine daemon {} (pid {}) - {} clients",
+                status.daemon_version,
+                status.pid,
+                status.clients.len()
+            );
+            if let Some(active) = status.clients.iter().find(|client| client.active) {
+                println!("Active: {}", active.title);
+            }
+            println!();
+            print_client_table(&status.clients);
+        }
+
+        "list" => {
+            let data = daemon::send_command(Command::List)?
+                .into_result()?
+                .context("Daemon returned no client list")?;
+
+            if args.iter().any(|arg| arg == "--json") {
+                println!("{}", data);
+                return Ok(());
+            }
+
+            let clients: Vec<ClientInfo> = serde_json::from_value(data)?;
+            print_client_table(&clients);
+        }
+
+        "subscribe" => {
+            let (snapshot, events) = daemon::subscribe()?;
+            println!("{}", serde_json::to_string(&snapshot)?);
+
+            for event in events {
+                println!("{}", serde_json::to_string(&event?)?);
+            }
+        }
+
+        "reload" => {
+            daemon::send_command(Command::Reload)?.into_result()?;
+            println!("✓ Reloaded config");
+        }
+
         "stop" => {
             println!("Stopping Nicotine...");
 
-            // Kill all nicotine processes
-            let _ = std::process::Command::new("pkill")
-                .arg("-9")
-                .arg("nicotine")
-                .output();
-
-            println!("✓ Nicotine stopped");
+            if daemon::stop(Duration::from_secs(3))? {
+                println!("✓ Nicotine stopped");
+            } else {
+                println!("Nicotine is not running");
+            }
 
-            // Clean up socket and lock files
-            let _ = std::fs::remove_file("/tmp/nicotine.sock");
-            let _ = std::fs::remove_file("/tmp/nicotine-cycle.lock");
+            let _ = runtime::remove_owned(&runtime::cycle_lock());
         }
 
         "init-config" => {
             Config::save_default()?;
         }
 
-        // Handle switch command or numeric shorthand
+        // Handle switch command, numeric shorthand and group commands
         cmd => {
-            // Check for "switch N" format
-            let target = if cmd == "switch" {
-                args.get(2).and_then(|s| s.parse::<usize>().ok())
+            let command = if cmd == "switch" {
+                // Check for "switch N" format
+                args.get(2)
+                    .and_then(|s| s.parse::<usize>().ok())
+                    .map(|target| Command::Switch {
+                        target,
+                        group: None,
+                    })
+            } else if let Ok(target) = cmd.parse::<usize>() {
+                // Just a number (shorthand)
+                Some(Command::Switch {
+                    target,
+                    group: None,
+                })
             } else {
-                // Check if it's just a number (shorthand)
-                cmd.parse::<usize>().ok()
+                // forward:GROUP, backward:GROUP, switch:GROUP:N or focus:NAME
+                Command::from_str(cmd).filter(|command| {
+                    matches!(
+                        command,
+                        Command::Forward { group: Some(_) }
+                            | Command::Backward { group: Some(_) }
+                            | Command::Switch { .. }
+                            | Command::Focus { .. }
+                    )
+                })
             };
 
-            if let Some(target) = target {
+            if let Some(command) = command {
                 // Try daemon first
-                if daemon::send_command(&format!("switch:{}", target)).is_ok() {
+                if let Some(response) = daemon::try_send_command(command.clone())? {
+                    response.into_result()?;
                     return Ok(());
                 }
 
                 // Fallback to direct mode
-                let lock_file = "/tmp/nicotine-cycle.lock";
-                let file = match OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .truncate(true)
-                    .mode(0o644)
-                    .open(lock_file)
-                {
-                    Ok(f) => f,
-                    Err(_) => return Ok(()),
-                };
-
-                #[allow(deprecated)]
-                if flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock).is_err() {
+                let Some(_lock) = try_cycle_lock() else {
                     return Ok(());
-                }
+                };
+                let wm = wm()?;
 
                 let mut state = CycleState::new();
+                state.set_rules(config.cycle_rules());
                 let windows = wm.get_eve_windows()?;
 
                 if windows.is_empty() {
@@ -325,16 +433,40 @@ fn main() -> Result<()> {
                 state.update_windows(windows);
 
                 if let Ok(active) = wm.get_active_window() {
-                    state.sync_with_active(active);
+                    state.sync_with_active(&active);
                 }
 
-                let character_order = Config::load_characters();
-                state.switch_to(
-                    target,
-                    &*wm,
-                    config.minimize_inactive,
-                    character_order.as_deref(),
-                )?;
+                let load_group = |name: &str| {
+                    Config::load_groups()
+                        .remove(name)
+                        .ok_or_else(|| SwitchError::GroupNotFound(name.to_string()))
+                };
+
+                match command {
+                    Command::Switch { target, group } => {
+                        let order = match group {
+                            Some(name) => Some(load_group(&name)?),
+                            None => Config::load_characters(),
+                        };
+                        state.switch_to(
+                            target,
+                            &*wm,
+                            config.minimize_inactive,
+                            order.as_deref(),
+                        )?;
+                    }
+                    Command::Focus { character } => {
+                        state.switch_to_character(&character, &*wm, config.minimize_inactive)?;
+                    }
+                    command => {
+                        if let Some((CycleScope::Group(name), steps)) =
+                            command.cycle_steps(config.cycle_mode)
+                        {
+                            let group = load_group(&name)?;
+                            state.cycle_group(steps, &group, &*wm, config.minimize_inactive)?;
+                        }
+                    }
+                }
             } else {
                 println!();
                 println!("🚬 N I C O T I N E 🚬");
@@ -344,13 +476,27 @@ fn main() -> Result<()> {
                 println!();
                 println!("Usage:");
                 println!("  nicotine start         - Start everything (daemon + overlay)");
-                println!("  nicotine stop          - Stop all Nicotine processes");
+                println!("  nicotine stop          - Stop the Nicotine daemon");
                 println!("  nicotine stack         - Stack all EVE windows");
                 println!("  nicotine forward       - Cycle forward");
                 println!("  nicotine backward      - Cycle backward");
+                println!(
+                    "  nicotine last          - Back to the previous client (again: further back)"
+                );
                 println!("  nicotine switch N      - Switch to client N (targeted cycling)");
                 println!("  nicotine N             - Shorthand for switch N");
+                println!(
+                    "  nicotine forward:G     - Cycle forward within group G (also backward:G)"
+                );
+                println!("  nicotine switch:G:N    - Switch to client N of group G");
+                println!("  nicotine focus:NAME    - Switch to the client of character NAME");
+                println!("  nicotine toggle_overlay - Hide or show the overlay");
+                println!("  nicotine status        - Show daemon status and clients (--json)");
+                println!("  nicotine list          - List tracked clients (--json)");
+                println!("  nicotine subscribe     - Stream daemon events as JSON lines");
+                println!("  nicotine reload        - Reload config.toml and characters.txt");
                 println!("  nicotine init-config   - Create default config.toml");
+                println!("  nicotine doctor        - Diagnose backend, input and socket problems");
                 println!();
                 println!("Advanced:");
                 println!("  nicotine daemon        - Start daemon only");
@@ -364,3 +510,48 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Take the direct-mode cycle lock so overlapping invocations don't fight.
+/// Returns None if another invocation holds it; the lock is released on drop.
+fn try_cycle_lock() -> Option<File> {
+    runtime::ensure_runtime_dir().ok()?;
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(runtime::cycle_lock())
+        .ok()?;
+
+    // Try to lock (non-blocking)
+    #[allow(deprecated)]
+    flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock).ok()?;
+
+    Some(file)
+}
+
+fn print_client_table(clients: &[ClientInfo]) {
+    if clients.is_empty() {
+        println!("No clients");
+        return;
+    }
+
+    println!("    #  SLOT  WINDOW          CHARACTER");
+    for client in clients {
+        let marker = if client.active { "▸" } else { " " };
+        let slot = client
+            .slot
+            .map(|slot| slot.to_string())
+            .unwrap_or_else(|| "-".to_string());
+
+        println!(
+            "{} {:>3}  {:<4}  {:<14}  {}",
+            marker,
+            client.index + 1,
+            slot,
+            client.id,
+            client.title
+        );
+    }
+}
@@ -1,225 +1,111 @@
 use crate::config::Config;
-use crate::cycle_state::CycleState;
-use crate::window_manager::WindowManager;
-use anyhow::{Context, Result};
-use evdev::{Device, InputEventKind, Key};
+use evdev::{Device, Key};
 use std::path::Path;
-use std::sync::{Arc, Mutex};
 
+/// Picks the mice whose buttons the binding engine reads
 pub struct MouseListener {
-    config: Config,
+    name: Option<String>,
+    path: Option<String>,
+    /// Take any mouse with side buttons
+    auto: bool,
 }
 
 impl MouseListener {
-    pub fn new(config: Config) -> Self {
-        Self { config }
-    }
-
-    /// Find mouse device by looking for devices with BTN_SIDE or BTN_EXTRA capabilities
     /// Priority order: configured device name -> configured device path -> auto-detect
-    fn find_mouse_device(
-        configured_name: Option<&str>,
-        configured_path: Option<&str>,
-    ) -> Result<Device> {
-        let devices_path = Path::new("/dev/input");
-
-        // 1. Try configured device name first (highest priority)
-        if let Some(device_name) = configured_name {
-            println!("Searching for device by name: {}", device_name);
-
-            for entry in std::fs::read_dir(devices_path)? {
-                let entry = entry?;
-                let path = entry.path();
-
-                if let Some(filename) = path.file_name() {
-                    if let Some(name) = filename.to_str() {
-                        if name.starts_with("event") {
-                            if let Ok(device) = Device::open(&path) {
-                                if let Some(dev_name) = device.name() {
-                                    if dev_name == device_name {
-                                        println!(
-                                            "Using configured mouse device by name: {} ({})",
-                                            dev_name,
-                                            path.display()
-                                        );
-                                        return Ok(device);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-
-            eprintln!(
-                "Warning: Failed to find device with name '{}'. Trying other methods...",
-                device_name
-            );
+    pub fn new(config: &Config) -> Self {
+        let configured = |value: &Option<String>| value.clone().filter(|value| !value.is_empty());
+        let name = configured(&config.mouse_device_name);
+        let path = configured(&config.mouse_device_path);
+        let auto = name.is_none() && path.is_none();
+
+        if let Some(name) = &name {
+            println!("Searching for mouse device by name: {}", name);
+        } else if let Some(path) = &path {
+            println!("Using configured mouse device by path: {}", path);
         }
 
-        // 2. Try configured path second
-        if let Some(path_str) = configured_path {
-            let path = Path::new(path_str);
-            match Device::open(path) {
-                Ok(device) => {
-                    println!(
-                        "Using configured mouse device by path: {} ({})",
-                        device.name().unwrap_or("Unknown"),
-                        path.display()
-                    );
-                    return Ok(device);
-                }
-                Err(e) => {
-                    eprintln!(
-                        "Warning: Failed to open configured mouse device '{}': {}",
-                        path_str, e
-                    );
-                    eprintln!("Falling back to automatic device detection...");
-                }
-            }
+        Self { name, path, auto }
+    }
+
+    /// Whether `device` at `path` is one of the mice to read
+    pub fn matches(&self, path: &Path, device: &Device) -> bool {
+        let side_buttons = device
+            .supported_keys()
+            .is_some_and(|keys| keys.contains(Key::BTN_SIDE) || keys.contains(Key::BTN_EXTRA));
+        self.is_mouse(device.name(), path, side_buttons)
+    }
+
+    fn is_mouse(&self, name: Option<&str>, path: &Path, side_buttons: bool) -> bool {
+        if self.name.is_some() && self.name.as_deref() == name {
+            return true;
         }
 
-        // 3. Fall back to automatic detection (lowest priority)
-        for entry in std::fs::read_dir(devices_path)? {
-            let entry = entry?;
-            let path = entry.path();
-
-            if let Some(filename) = path.file_name() {
-                if let Some(name) = filename.to_str() {
-                    if name.starts_with("event") {
-                        if let Ok(device) = Device::open(&path) {
-                            // Check if device has mouse side buttons
-                            if device.supported_keys().is_some_and(|keys| {
-                                keys.contains(Key::BTN_SIDE) || keys.contains(Key::BTN_EXTRA)
-                            }) {
-                                println!(
-                                    "Found mouse device: {} ({})",
-                                    device.name().unwrap_or("Unknown"),
-                                    path.display()
-                                );
-                                return Ok(device);
-                            }
-                        }
-                    }
-                }
-            }
+        // Resolved each time, so /dev/input/by-id links follow the device
+        let configured_path = self
+            .path
+            .as_ref()
+            .map(|configured| std::fs::canonicalize(configured).unwrap_or(configured.into()));
+        if configured_path.is_some_and(|configured| configured == path) {
+            return true;
         }
 
-        anyhow::bail!("No mouse device with side buttons found in /dev/input")
+        self.auto && side_buttons
     }
 
-    /// Run the mouse event listener in a background thread
-    pub fn spawn(
-        &self,
-        wm: Arc<dyn WindowManager>,
-        state: Arc<Mutex<CycleState>>,
-    ) -> Result<std::thread::JoinHandle<()>> {
-        if !self.config.enable_mouse_buttons {
-            anyhow::bail!("Mouse buttons are disabled in config");
-        }
-
-        let forward_button = self.config.forward_button;
-        let backward_button = self.config.backward_button;
-        let mouse_device_name = self.config.mouse_device_name.clone();
-        let mouse_device_path = self.config.mouse_device_path.clone();
-        let minimize_inactive = self.config.minimize_inactive;
-
-        let handle = std::thread::spawn(move || {
-            match Self::run_listener(
-                wm,
-                state,
-                forward_button,
-                backward_button,
-                mouse_device_name,
-                mouse_device_path,
-                minimize_inactive,
-            ) {
-                Ok(_) => println!("Mouse listener stopped"),
-                Err(e) => eprintln!("Mouse listener error: {}", e),
-            }
-        });
-
-        Ok(handle)
+    /// Whether a specific device was configured, rather than any mouse
+    pub fn is_configured(&self) -> bool {
+        !self.auto
     }
 
-    fn run_listener(
-        wm: Arc<dyn WindowManager>,
-        state: Arc<Mutex<CycleState>>,
-        forward_button: u16,
-        backward_button: u16,
-        mouse_device_name: Option<String>,
-        mouse_device_path: Option<String>,
-        minimize_inactive: bool,
-    ) -> Result<()> {
-        let mut device = Self::find_mouse_device(
-            mouse_device_name.as_deref(),
-            mouse_device_path.as_deref(),
-        )
-        .context(
-            "Failed to find mouse device. Make sure you have permission to read /dev/input/event*",
-        )?;
-
-        // DON'T grab the device - we only want to passively listen to events
-        // Grabbing would prevent normal mouse usage!
-
-        println!(
-            "Listening for mouse buttons: forward={}, backward={}",
-            forward_button, backward_button
+    /// The configured device isn't there: take any mouse with side buttons
+    /// too, while still picking up the configured one when it appears
+    pub fn fall_back_to_auto(&mut self) {
+        let configured = self.name.as_ref().or(self.path.as_ref());
+        eprintln!(
+            "Warning: Failed to find mouse device '{}'. Falling back to automatic device detection...",
+            configured.map(String::as_str).unwrap_or_default()
         );
-
-        loop {
-            for event in device.fetch_events()? {
-                if let InputEventKind::Key(key) = event.kind() {
-                    let code = key.code();
-
-                    // Only handle button press (value 1), ignore release (value 0)
-                    if event.value() == 1 {
-                        if code == forward_button {
-                            println!("Forward button pressed");
-                            if let Err(e) = Self::cycle_forward(&wm, &state, minimize_inactive) {
-                                eprintln!("Failed to cycle forward: {}", e);
-                            }
-                        } else if code == backward_button {
-                            println!("Backward button pressed");
-                            if let Err(e) = Self::cycle_backward(&wm, &state, minimize_inactive) {
-                                eprintln!("Failed to cycle backward: {}", e);
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        self.auto = true;
     }
+}
 
-    fn cycle_forward(
-        wm: &Arc<dyn WindowManager>,
-        state: &Arc<Mutex<CycleState>>,
-        minimize_inactive: bool,
-    ) -> Result<()> {
-        let mut state = state.lock().unwrap();
-
-        // Sync with active window first
-        if let Ok(active) = wm.get_active_window() {
-            state.sync_with_active(active);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
 
-        state.cycle_forward(&**wm, minimize_inactive)?;
-        Ok(())
+    fn listener(name: Option<&str>, path: Option<&str>) -> MouseListener {
+        let mut config = test_config("");
+        config.mouse_device_name = name.map(str::to_string);
+        config.mouse_device_path = path.map(str::to_string);
+        MouseListener::new(&config)
     }
 
-    fn cycle_backward(
-        wm: &Arc<dyn WindowManager>,
-        state: &Arc<Mutex<CycleState>>,
-        minimize_inactive: bool,
-    ) -> Result<()> {
-        let mut state = state.lock().unwrap();
+    #[test]
+    fn test_configured_name_wins() {
+        let mut mouse = listener(Some("Logitech PRO X"), None);
+        let event5 = Path::new("/dev/input/event5");
 
-        // Sync with active window first
-        if let Ok(active) = wm.get_active_window() {
-            state.sync_with_active(active);
-        }
+        assert!(mouse.is_mouse(Some("Logitech PRO X"), event5, false));
+        assert!(!mouse.is_mouse(Some("Razer Naga"), event5, true));
+
+        // Without the configured mouse, any mouse with side buttons will do
+        mouse.fall_back_to_auto();
+        assert!(mouse.is_mouse(Some("Razer Naga"), event5, true));
+        assert!(mouse.is_mouse(Some("Logitech PRO X"), event5, false));
+        assert!(!mouse.is_mouse(Some("Keyboard"), event5, false));
+    }
 
-        state.cycle_backward(&**wm, minimize_inactive)?;
-        Ok(())
+    #[test]
+    fn test_configured_path_and_auto() {
+        let mouse = listener(None, Some("/dev/input/event3"));
+        assert!(mouse.is_mouse(None, Path::new("/dev/input/event3"), false));
+        assert!(!mouse.is_mouse(None, Path::new("/dev/input/event4"), true));
+
+        // An empty name counts as unset
+        let mouse = listener(Some(""), None);
+        assert!(!mouse.is_configured());
+        assert!(mouse.is_mouse(None, Path::new("/dev/input/event4"), true));
+        assert!(!mouse.is_mouse(Some(""), Path::new("/dev/input/event4"), false));
     }
 }
@@ -1,18 +1,40 @@
 use crate::cycle_state::CycleState;
-use crate::window_manager::WindowManager;
+use crate::daemon;
+use crate::events::Event;
+use crate::layout::{self, LayoutStore, Monitor, Rect};
+use crate::protocol::{ClientInfo, Command};
+use crate::window_manager::{Capabilities, EveWindow, WindowHandle, WindowManager};
+use anyhow::Context;
 use eframe::egui;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 pub struct OverlayApp {
     wm: Arc<dyn WindowManager>,
+    capabilities: Capabilities,
     state: Arc<Mutex<CycleState>>,
     config: crate::config::Config,
     drag_start_window_pos: Option<egui::Pos2>,
     drag_accumulated: egui::Vec2,
-    overlay_window_id: Option<u32>,
+    drag_snap: Option<DragSnap>,
+    drag_rect: Option<Rect>,
+    overlay_window_id: Option<WindowHandle>,
+    layout_store: LayoutStore,
+    monitors: Vec<Monitor>,
+    setup: String,
+    monitor_changes: Receiver<Vec<Monitor>>,
     last_sync: Instant,
     last_index: usize,
+    last_client_count: Option<usize>,
+    subscribed: Arc<AtomicBool>,
+}
+
+/// What a dragged overlay snaps to, looked up once when the drag starts
+struct DragSnap {
+    others: Vec<Rect>,
+    screens: Vec<Rect>,
 }
 
 impl OverlayApp {
@@ -21,6 +43,7 @@ impl OverlayApp {
         wm: Arc<dyn WindowManager>,
         state: Arc<Mutex<CycleState>>,
         config: crate::config::Config,
+        monitors: Vec<Monitor>,
     ) -> Self {
         let mut fonts = egui::FontDefinitions::default();
 
@@ -56,15 +79,36 @@ impl OverlayApp {
 
         cc.egui_ctx.set_fonts(fonts);
 
+        let subscribed = Arc::new(AtomicBool::new(false));
+        follow_daemon(Arc::clone(&wm), Arc::clone(&state), Arc::clone(&subscribed));
+        let monitor_changes = layout::watch_monitors(config.clone(), monitors.clone());
+
+        let capabilities = wm.capabilities();
+        if !capabilities.overlay_dragging {
+            println!(
+                "{}; middle-drag lets the compositor move it, but the position isn't saved",
+                wm.unsupported("Placing the overlay")
+            );
+        }
+
         Self {
             wm,
+            capabilities,
             state,
             config,
             drag_start_window_pos: None,
             drag_accumulated: egui::Vec2::ZERO,
+            drag_snap: None,
+            drag_rect: None,
             overlay_window_id: None,
+            layout_store: LayoutStore::new(),
+            setup: layout::setup_id(&monitors),
+            monitors,
+            monitor_changes,
             last_sync: Instant::now(),
             last_index: 0,
+            last_client_count: None,
+            subscribed,
         }
     }
 }
@@ -74,39 +118,58 @@ impl eframe::App for OverlayApp {
         // Request repaint for smooth updates
         ctx.request_repaint();
 
-        // Read current index from file (instant, no process spawning)
-        if let Some(index) = CycleState::read_index_from_file() {
-            if index != self.last_index {
-                self.last_index = index;
-                let mut state = self.state.lock().unwrap();
-                state.set_current_index(index);
+        // Docked or undocked: move to where the overlay was left on this setup
+        if let Some(monitors) = self.monitor_changes.try_iter().last() {
+            let setup = layout::setup_id(&monitors);
+            if let Some(rect) = self.layout_store.load(&setup).overlay {
+                ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(egui::pos2(
+                    rect.x as f32,
+                    rect.y as f32,
+                )));
             }
+            self.setup = setup;
+            self.monitors = monitors;
         }
 
-        // Periodic full sync for window list updates (new clients, etc)
-        let now = Instant::now();
-        if now.duration_since(self.last_sync).as_millis() >= 500 {
-            self.last_sync = now;
-
-            if let Ok(windows) = self.wm.get_eve_windows() {
-                let mut state = self.state.lock().unwrap();
-                state.update_windows(windows);
-
-                // Resize window based on client count
-                let client_count = state.get_windows().len();
-                let base_height = 320.0_f32;
-                let per_client = 20.0_f32;
-                let min_clients = 10;
-                let extra_clients = client_count.saturating_sub(min_clients);
-                let target_height = base_height + (extra_clients as f32 * per_client);
-
-                ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(
-                    220.0,
-                    target_height,
-                )));
+        // Without a daemon to follow, fall back to polling
+        if !self.subscribed.load(Ordering::Relaxed) {
+            // Read current index from file (instant, no process spawning)
+            if let Some(index) = CycleState::read_index_from_file() {
+                if index != self.last_index {
+                    self.last_index = index;
+                    let mut state = self.state.lock().unwrap();
+                    state.set_current_index(index);
+                }
+            }
+
+            // Periodic full sync for window list updates (new clients, etc)
+            let now = Instant::now();
+            if now.duration_since(self.last_sync).as_millis() >= 500 {
+                self.last_sync = now;
+
+                if let Ok(windows) = self.wm.get_eve_windows() {
+                    self.state.lock().unwrap().update_windows(windows);
+                }
             }
         }
 
+        // Resize window based on client count
+        let client_count = self.state.lock().unwrap().get_windows().len();
+        if self.last_client_count != Some(client_count) {
+            self.last_client_count = Some(client_count);
+
+            let base_height = 320.0_f32;
+            let per_client = 20.0_f32;
+            let min_clients = 10;
+            let extra_clients = client_count.saturating_sub(min_clients);
+            let target_height = base_height + (extra_clients as f32 * per_client);
+
+            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(
+                220.0,
+                target_height,
+            )));
+        }
+
         let red = egui::Color32::from_rgb(196, 30, 58);
         let gold = egui::Color32::from_rgb(180, 155, 105);
         let cream = egui::Color32::from_rgb(252, 250, 242);
@@ -183,10 +246,19 @@ impl eframe::App for OverlayApp {
                             .fill(red)
                             .rounding(2.0);
 
-                    if ui.add(button).clicked() {
+                    let clicked = ui
+                        .add_enabled(self.capabilities.stacking, button)
+                        .on_disabled_hover_text(self.wm.unsupported("Window stacking"))
+                        .clicked();
+
+                    if clicked {
                         let wm_clone = Arc::clone(&self.wm);
                         let config = self.config.clone();
                         std::thread::spawn(move || {
+                            // Prefer the daemon so subscribers hear about it
+                            if daemon::send_command(Command::Stack).is_ok() {
+                                return;
+                            }
                             if let Ok(windows) = wm_clone.get_eve_windows() {
                                 let _ = wm_clone.stack_windows(&windows, &config);
                             }
@@ -198,8 +270,14 @@ impl eframe::App for OverlayApp {
             });
 
         // Handle dragging with middle mouse button
-        // Note: Overlay dragging is X11-only. On Wayland, use your compositor's window
-        // management features to position the overlay window.
+        if !self.capabilities.overlay_dragging {
+            // Hand the move to the compositor; nicotine can't place or track it
+            if ctx.input(|i| i.pointer.button_pressed(egui::PointerButton::Middle)) {
+                ctx.send_viewport_cmd(egui::ViewportCommand::StartDrag);
+            }
+            return;
+        }
+
         let middle_down = ctx.input(|i| i.pointer.button_down(egui::PointerButton::Middle));
 
         if middle_down {
@@ -209,6 +287,17 @@ impl eframe::App for OverlayApp {
                     self.drag_start_window_pos = Some(window_pos);
                     self.drag_accumulated = egui::Vec2::ZERO;
 
+                    // Snap to previews and screen edges
+                    self.drag_snap = Some(DragSnap {
+                        others: self
+                            .layout_store
+                            .load(&self.setup)
+                            .characters
+                            .into_values()
+                            .collect(),
+                        screens: self.monitors.iter().map(|m| m.rect).collect(),
+                    });
+
                     // Cache the window ID once at the start
                     if self.overlay_window_id.is_none() {
                         if let Ok(Some(id)) = self.wm.find_window_by_title("Nicotine") {
@@ -225,20 +314,38 @@ impl eframe::App for OverlayApp {
 
                 // Use cached window ID for instant movement
                 if let (Some(start_window), Some(window_id)) =
-                    (self.drag_start_window_pos, self.overlay_window_id)
+                    (self.drag_start_window_pos, &self.overlay_window_id)
                 {
                     let new_x = (start_window.x + self.drag_accumulated.x) as i32;
                     let new_y = (start_window.y + self.drag_accumulated.y) as i32;
 
-                    let _ = self.wm.move_window(window_id, new_x, new_y);
+                    let size = ctx
+                        .input(|i| i.viewport().outer_rect)
+                        .map(|r| r.size())
+                        .unwrap_or_default();
+                    let mut rect = Rect::new(new_x, new_y, size.x as u32, size.y as u32);
+                    if let Some(snap) = &self.drag_snap {
+                        rect = layout::snap(rect, &snap.others, &snap.screens);
+                    }
+
+                    let _ = self.wm.move_window(window_id, rect.x, rect.y);
+                    self.drag_rect = Some(rect);
                 }
             }
 
             ctx.set_cursor_icon(egui::CursorIcon::Grabbing);
         } else {
+            // Remember where the overlay was dropped for this monitor setup
+            if let Some(rect) = self.drag_rect.take() {
+                if let Err(e) = self.layout_store.save_overlay(&self.setup, rect) {
+                    eprintln!("Failed to save overlay position: {}", e);
+                }
+            }
+
             // Reset drag state when button is released
             self.drag_start_window_pos = None;
             self.drag_accumulated = egui::Vec2::ZERO;
+            self.drag_snap = None;
 
             if ctx.input(|i| i.pointer.hover_pos()).is_some() {
                 ctx.set_cursor_icon(egui::CursorIcon::Grab);
@@ -247,6 +354,83 @@ impl eframe::App for OverlayApp {
     }
 }
 
+/// Follow the daemon's event stream in the background so the overlay doesn't
+/// have to poll. Reconnects if the daemon isn't running yet or restarts.
+fn follow_daemon(
+    wm: Arc<dyn WindowManager>,
+    state: Arc<Mutex<CycleState>>,
+    subscribed: Arc<AtomicBool>,
+) {
+    let mut hidden = false;
+    std::thread::spawn(move || loop {
+        if let Ok((snapshot, events)) = daemon::subscribe() {
+            apply_clients(&state, &snapshot.clients);
+            subscribed.store(true, Ordering::Relaxed);
+
+            for event in events {
+                match event {
+                    Ok(Event::ActiveChanged { index, .. }) => {
+                        state.lock().unwrap().set_current_index(index);
+                    }
+                    Ok(Event::ClientAdded { .. }) | Ok(Event::ClientRemoved { .. }) => {
+                        // Take the daemon's order rather than guessing where the client goes
+                        if let Ok(Some(data)) =
+                            daemon::send_command(Command::List).and_then(|r| r.into_result())
+                        {
+                            if let Ok(clients) = serde_json::from_value::<Vec<ClientInfo>>(data) {
+                                apply_clients(&state, &clients);
+                            }
+                        }
+                    }
+                    Ok(Event::OverlayToggled) => match toggle_overlay(&*wm, hidden) {
+                        Ok(()) => hidden = !hidden,
+                        Err(e) => eprintln!("Failed to toggle the overlay: {}", e),
+                    },
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+            }
+
+            subscribed.store(false, Ordering::Relaxed);
+        }
+
+        std::thread::sleep(Duration::from_secs(1));
+    });
+}
+
+/// Minimize the overlay window, or bring it back if it's `hidden`
+fn toggle_overlay(wm: &dyn WindowManager, hidden: bool) -> anyhow::Result<()> {
+    if !wm.capabilities().minimize {
+        anyhow::bail!(wm.unsupported("Hiding the overlay"));
+    }
+
+    let id = wm
+        .find_window_by_title("Nicotine")?
+        .context("Overlay window not found")?;
+    if hidden {
+        wm.restore_window(&id)
+    } else {
+        wm.minimize_window(&id)
+    }
+}
+
+fn apply_clients(state: &Mutex<CycleState>, clients: &[ClientInfo]) {
+    let windows = clients
+        .iter()
+        .map(|client| EveWindow {
+            id: client.id.clone(),
+            title: client.title.clone(),
+        })
+        .collect();
+
+    let mut state = state.lock().unwrap();
+    state.update_windows(windows);
+    // By id, as the daemon may have been reloaded with another cycle order
+    if let Some(active) = clients.iter().find(|client| client.active) {
+        state.sync_with_active(&active.id);
+    }
+}
+
 pub fn run_overlay(
     wm: Arc<dyn WindowManager>,
     state: Arc<Mutex<CycleState>>,
@@ -254,6 +438,14 @@ pub fn run_overlay(
     overlay_y: f32,
     config: crate::config::Config,
 ) -> Result<(), eframe::Error> {
+    // Start where the overlay was last left on this monitor setup
+    let monitors = layout::detect_monitors(&config);
+    let setup = layout::setup_id(&monitors);
+    let (overlay_x, overlay_y) = match LayoutStore::new().load(&setup).overlay {
+        Some(rect) => (rect.x as f32, rect.y as f32),
+        None => (overlay_x, overlay_y),
+    };
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([220.0, 320.0])
@@ -284,7 +476,7 @@ pub fn run_overlay(
                     }
                 }
             });
-            Ok(Box::new(OverlayApp::new(cc, wm, state, config)))
+            Ok(Box::new(OverlayApp::new(cc, wm, state, config, monitors)))
         }),
     )
 }
@@ -0,0 +1,806 @@
+use crate::config::Config;
+use crate::cycle_state::CycleState;
+use crate::events::Event;
+use crate::layout::{self, LayoutStore, Monitor, Rect};
+use crate::protocol::Command;
+use crate::window_manager::{detect_display_server, DisplayServer, EveWindow};
+use crate::x11_manager::x11_window;
+use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use x11rb::connection::Connection;
+use x11rb::properties::{WmSizeHints, WmSizeHintsSpecification};
+use x11rb::protocol::composite::{ConnectionExt as _, Redirect};
+use x11rb::protocol::damage::{ConnectionExt as _, Damage, ReportLevel};
+use x11rb::protocol::randr::{ConnectionExt as _, NotifyMask};
+use x11rb::protocol::render::{
+    ConnectionExt as _, CreatePictureAux, PictOp, Pictformat, Picture, QueryPictFormatsReply,
+    Transform,
+};
+use x11rb::protocol::xproto::*;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::NONE;
+
+/// Upper bound on how often thumbnails are redrawn (~20 fps)
+const FRAME_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Space between thumbnails when laying them out
+const PREVIEW_GAP: i32 = 8;
+
+/// Width of the border drawn around the active client's thumbnail
+const ACTIVE_BORDER: u16 = 3;
+
+x11rb::atom_manager! {
+    PreviewAtoms: PreviewAtomsCookie {
+        _NET_WM_NAME,
+        _NET_WM_STATE,
+        _NET_WM_STATE_ABOVE,
+        _NET_WM_STATE_STICKY,
+        _NET_WM_STATE_SKIP_TASKBAR,
+        _NET_WM_STATE_SKIP_PAGER,
+        _NET_WM_WINDOW_TYPE,
+        _NET_WM_WINDOW_TYPE_UTILITY,
+        _MOTIF_WM_HINTS,
+        UTF8_STRING,
+    }
+}
+
+/// One live thumbnail: a borderless window showing a scaled copy of a client
+struct Thumbnail {
+    source: Window,
+    title: String,
+    window: Window,
+    picture: Picture,
+    source_picture: Picture,
+    damage: Damage,
+    source_size: (u16, u16),
+    rect: Rect,
+    dirty: bool,
+}
+
+/// A thumbnail being moved with the middle mouse button
+struct Drag {
+    window: Window,
+    pointer: (i32, i32),
+    origin: Rect,
+    /// Windows to snap against, fixed for the duration of the drag
+    others: Vec<Rect>,
+}
+
+/// Live, clickable thumbnails of every EVE client (X11 only).
+/// Clients are redirected with XComposite so their contents stay available,
+/// scaled with XRender and redrawn whenever XDamage reports a change.
+pub struct Previews {
+    conn: RustConnection,
+    screen_num: usize,
+    atoms: PreviewAtoms,
+    formats: QueryPictFormatsReply,
+    label_gc: Gcontext,
+    border_gc: Gcontext,
+    thumbnails: Vec<Thumbnail>,
+    active: Option<Window>,
+    state: Arc<Mutex<CycleState>>,
+    config: Config,
+    store: LayoutStore,
+    monitors: Vec<Monitor>,
+    setup: String,
+    drag: Option<Drag>,
+    /// Runs a command on the daemon's worker, like a socket client would
+    dispatch: Box<dyn Fn(Command) + Send>,
+}
+
+impl Previews {
+    fn connect(
+        state: Arc<Mutex<CycleState>>,
+        config: Config,
+        dispatch: Box<dyn Fn(Command) + Send>,
+    ) -> Result<Self> {
+        if detect_display_server() != DisplayServer::X11 {
+            anyhow::bail!("Client previews are only supported on X11");
+        }
+
+        let (conn, screen_num) =
+            RustConnection::connect(None).context("Failed to connect to X11 server")?;
+
+        conn.composite_query_version(0, 4)?
+            .reply()
+            .context("XComposite extension not available")?;
+        conn.render_query_version(0, 11)?
+            .reply()
+            .context("XRender extension not available")?;
+        conn.damage_query_version(1, 1)?
+            .reply()
+            .context("XDamage extension not available")?;
+
+        let atoms = PreviewAtoms::new(&conn)?.reply()?;
+        let formats = conn.render_query_pict_formats()?.reply()?;
+
+        let screen = &conn.setup().roots[screen_num];
+        let root = screen.root;
+        let (white, black) = (screen.white_pixel, screen.black_pixel);
+
+        // Same red as the overlay's header
+        let red = conn
+            .alloc_color(screen.default_colormap, 196 << 8, 30 << 8, 58 << 8)?
+            .reply()
+            .map(|color| color.pixel)
+            .unwrap_or(white);
+
+        let font = conn.generate_id()?;
+        conn.open_font(font, b"fixed")?;
+
+        let label_gc = conn.generate_id()?;
+        conn.create_gc(
+            label_gc,
+            root,
+            &CreateGCAux::new()
+                .foreground(white)
+                .background(black)
+                .font(font),
+        )?;
+
+        // Follow docking/undocking so the right layout gets applied
+        if conn.randr_query_version(1, 2)?.reply().is_ok() {
+            conn.randr_select_input(root, NotifyMask::SCREEN_CHANGE)?;
+        }
+
+        let monitors = layout::detect_monitors(&config);
+        let setup = layout::setup_id(&monitors);
+
+        let border_gc = conn.generate_id()?;
+        conn.create_gc(
+            border_gc,
+            root,
+            &CreateGCAux::new()
+                .foreground(red)
+                .line_width(u32::from(ACTIVE_BORDER)),
+        )?;
+
+        Ok(Self {
+            conn,
+            screen_num,
+            atoms,
+            formats,
+            label_gc,
+            border_gc,
+            thumbnails: Vec::new(),
+            active: None,
+            state,
+            config,
+            store: LayoutStore::new(),
+            monitors,
+            setup,
+            drag: None,
+            dispatch,
+        })
+    }
+
+    /// Run the thumbnails in a background thread until `running` is cleared.
+    /// `events` should be a subscription to the daemon's event bus, and
+    /// `dispatch` queues a thumbnail click's switch on the daemon's worker.
+    pub fn spawn(
+        state: Arc<Mutex<CycleState>>,
+        events: Receiver<Event>,
+        config: Config,
+        running: Arc<AtomicBool>,
+        dispatch: impl Fn(Command) + Send + 'static,
+    ) -> Result<std::thread::JoinHandle<()>> {
+        if !config.show_previews {
+            anyhow::bail!("Client previews are disabled in config");
+        }
+
+        let mut previews = Self::connect(state, config, Box::new(dispatch))?;
+
+        let handle = std::thread::spawn(move || match previews.run(events, running) {
+            Ok(_) => println!("Client previews stopped"),
+            Err(e) => eprintln!("Client preview error: {}", e),
+        });
+
+        Ok(handle)
+    }
+
+    fn run(&mut self, events: Receiver<Event>, running: Arc<AtomicBool>) -> Result<()> {
+        {
+            let state = self.state.lock().unwrap();
+            self.active = state
+                .get_windows()
+                .get(state.get_current_index())
+                .and_then(|w| x11_window(&w.id).ok());
+        }
+        self.sync_clients()?;
+
+        while running.load(Ordering::SeqCst) {
+            let mut clients_changed = false;
+
+            for event in events.try_iter() {
+                match event {
+                    Event::ActiveChanged { id, .. } => {
+                        if let Ok(window) = x11_window(&id) {
+                            self.set_active(window);
+                        }
+                    }
+                    Event::ClientAdded { .. } | Event::ClientRemoved { .. } => {
+                        clients_changed = true;
+                    }
+                    _ => {}
+                }
+            }
+
+            if clients_changed {
+                self.sync_clients()?;
+            }
+
+            while let Some(event) = self.conn.poll_for_event()? {
+                self.handle_event(event)?;
+            }
+
+            self.draw_dirty()?;
+            self.conn.flush()?;
+
+            std::thread::sleep(FRAME_INTERVAL);
+        }
+
+        Ok(())
+    }
+
+    /// Add, remove, retitle and lay out thumbnails to match the tracked clients
+    fn sync_clients(&mut self) -> Result<()> {
+        let windows = self.state.lock().unwrap().get_windows().to_vec();
+
+        let (kept, removed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.thumbnails)
+            .into_iter()
+            .partition(|thumbnail| windows.iter().any(|w| w.id == thumbnail.source.into()));
+
+        for thumbnail in removed {
+            self.destroy_thumbnail(thumbnail)?;
+        }
+        self.thumbnails = kept;
+
+        // Saved positions win, everything else goes in the default row
+        let saved = self.store.load(&self.setup);
+
+        for (index, window) in windows.iter().enumerate() {
+            let rect = saved
+                .characters
+                .get(&window.title)
+                .copied()
+                .unwrap_or_else(|| default_rect(&self.config, index));
+
+            match self
+                .thumbnails
+                .iter()
+                .position(|t| window.id == t.source.into())
+            {
+                Some(existing) => {
+                    if self.thumbnails[existing].title != window.title {
+                        self.set_title(self.thumbnails[existing].window, &window.title)?;
+                        self.thumbnails[existing].title = window.title.clone();
+                        self.thumbnails[existing].dirty = true;
+                    }
+                    self.place(existing, rect)?;
+                }
+                None => match self.create_thumbnail(window, rect) {
+                    Ok(thumbnail) => self.thumbnails.push(thumbnail),
+                    // The client may have closed in the meantime
+                    Err(e) => eprintln!("Failed to preview '{}': {}", window.title, e),
+                },
+            }
+        }
+
+        // Keep thumbnails in cycle order
+        self.thumbnails.sort_by_key(|thumbnail| {
+            windows
+                .iter()
+                .position(|w| w.id == thumbnail.source.into())
+                .unwrap_or(usize::MAX)
+        });
+
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Move and resize an existing thumbnail, unless it's being dragged
+    fn place(&mut self, index: usize, rect: Rect) -> Result<()> {
+        let thumbnail = &self.thumbnails[index];
+        let dragging = self
+            .drag
+            .as_ref()
+            .is_some_and(|drag| drag.window == thumbnail.window);
+        if dragging || thumbnail.rect == rect {
+            return Ok(());
+        }
+
+        self.conn.configure_window(
+            thumbnail.window,
+            &ConfigureWindowAux::new()
+                .x(rect.x)
+                .y(rect.y)
+                .width(rect.width)
+                .height(rect.height),
+        )?;
+
+        let resized = (thumbnail.rect.width, thumbnail.rect.height) != (rect.width, rect.height);
+        self.thumbnails[index].rect = rect;
+
+        if resized {
+            self.set_transform(&self.thumbnails[index])?;
+            let thumbnail = &mut self.thumbnails[index];
+            self.conn.clear_area(false, thumbnail.window, 0, 0, 0, 0)?;
+            thumbnail.dirty = true;
+        }
+
+        Ok(())
+    }
+
+    fn create_thumbnail(&self, client: &EveWindow, rect: Rect) -> Result<Thumbnail> {
+        let screen = &self.conn.setup().roots[self.screen_num];
+        let (x, y) = (rect.x, rect.y);
+        let (width, height) = thumbnail_size(&rect);
+        let source = x11_window(&client.id)?;
+
+        // Keep an offscreen copy of the client; the server still paints it as usual
+        self.conn
+            .composite_redirect_window(source, Redirect::AUTOMATIC)?;
+        self.conn.change_window_attributes(
+            source,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY),
+        )?;
+
+        let geometry = self.conn.get_geometry(source)?.reply()?;
+        let attributes = self.conn.get_window_attributes(source)?.reply()?;
+
+        let window = self.conn.generate_id()?;
+        self.conn.create_window(
+            x11rb::COPY_DEPTH_FROM_PARENT,
+            window,
+            screen.root,
+            x as i16,
+            y as i16,
+            width,
+            height,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            screen.root_visual,
+            &CreateWindowAux::new()
+                .background_pixel(screen.black_pixel)
+                .event_mask(
+                    EventMask::EXPOSURE
+                        | EventMask::BUTTON_PRESS
+                        | EventMask::BUTTON_RELEASE
+                        | EventMask::BUTTON2_MOTION,
+                ),
+        )?;
+
+        self.set_title(window, &client.title)?;
+        self.conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_CLASS,
+            AtomEnum::STRING,
+            b"nicotine-preview\0Nicotine\0",
+        )?;
+
+        // Borderless, always on top, on every desktop and out of the taskbar
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.atoms._MOTIF_WM_HINTS,
+            self.atoms._MOTIF_WM_HINTS,
+            &[2, 0, 0, 0, 0],
+        )?;
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.atoms._NET_WM_WINDOW_TYPE,
+            AtomEnum::ATOM,
+            &[self.atoms._NET_WM_WINDOW_TYPE_UTILITY],
+        )?;
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.atoms._NET_WM_STATE,
+            AtomEnum::ATOM,
+            &[
+                self.atoms._NET_WM_STATE_ABOVE,
+                self.atoms._NET_WM_STATE_STICKY,
+                self.atoms._NET_WM_STATE_SKIP_TASKBAR,
+                self.atoms._NET_WM_STATE_SKIP_PAGER,
+            ],
+        )?;
+
+        // Ask the window manager to respect our placement
+        let mut hints = WmSizeHints::new();
+        hints.position = Some((WmSizeHintsSpecification::UserSpecified, x, y));
+        hints.size = Some((
+            WmSizeHintsSpecification::UserSpecified,
+            i32::from(width),
+            i32::from(height),
+        ));
+        hints.set_normal_hints(&self.conn, window)?;
+
+        self.conn.map_window(window)?;
+
+        let picture = self.conn.generate_id()?;
+        self.conn.render_create_picture(
+            picture,
+            window,
+            self.format_for(screen.root_visual)?,
+            &CreatePictureAux::new(),
+        )?;
+
+        let source_picture = self.conn.generate_id()?;
+        self.conn.render_create_picture(
+            source_picture,
+            source,
+            self.format_for(attributes.visual)?,
+            &CreatePictureAux::new().subwindowmode(SubwindowMode::INCLUDE_INFERIORS),
+        )?;
+        self.conn
+            .render_set_picture_filter(source_picture, b"bilinear", &[])?;
+
+        let damage = self.conn.generate_id()?;
+        self.conn
+            .damage_create(damage, source, ReportLevel::NON_EMPTY)?;
+
+        let thumbnail = Thumbnail {
+            source,
+            title: client.title.clone(),
+            window,
+            picture,
+            source_picture,
+            damage,
+            source_size: (geometry.width, geometry.height),
+            rect,
+            dirty: true,
+        };
+        self.set_transform(&thumbnail)?;
+
+        Ok(thumbnail)
+    }
+
+    fn destroy_thumbnail(&self, thumbnail: Thumbnail) -> Result<()> {
+        // The client may already be gone, in which case these fail harmlessly
+        self.conn.damage_destroy(thumbnail.damage)?;
+        self.conn.render_free_picture(thumbnail.source_picture)?;
+        self.conn.render_free_picture(thumbnail.picture)?;
+        self.conn.destroy_window(thumbnail.window)?;
+        self.conn
+            .composite_unredirect_window(thumbnail.source, Redirect::AUTOMATIC)?;
+        Ok(())
+    }
+
+    fn set_title(&self, window: Window, title: &str) -> Result<()> {
+        // Deliberately not "EVE - ..." so the thumbnail isn't mistaken for a client
+        let title = format!("Nicotine Preview - {}", title);
+        self.conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_NAME,
+            AtomEnum::STRING,
+            title.as_bytes(),
+        )?;
+        self.conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            self.atoms._NET_WM_NAME,
+            self.atoms.UTF8_STRING,
+            title.as_bytes(),
+        )?;
+        Ok(())
+    }
+
+    /// Scale the client down to the thumbnail size in one XRender transform
+    fn set_transform(&self, thumbnail: &Thumbnail) -> Result<()> {
+        let (scale, ..) = fit(thumbnail.source_size, thumbnail_size(&thumbnail.rect));
+
+        self.conn.render_set_picture_transform(
+            thumbnail.source_picture,
+            Transform {
+                matrix11: to_fixed(scale),
+                matrix12: 0,
+                matrix13: 0,
+                matrix21: 0,
+                matrix22: to_fixed(scale),
+                matrix23: 0,
+                matrix31: 0,
+                matrix32: 0,
+                matrix33: to_fixed(1.0),
+            },
+        )?;
+        Ok(())
+    }
+
+    fn format_for(&self, visual: Visualid) -> Result<Pictformat> {
+        self.formats
+            .screens
+            .iter()
+            .flat_map(|screen| &screen.depths)
+            .flat_map(|depth| &depth.visuals)
+            .find(|v| v.visual == visual)
+            .map(|v| v.format)
+            .ok_or_else(|| anyhow::anyhow!("No XRender format for visual {:#x}", visual))
+    }
+
+    fn set_active(&mut self, id: Window) {
+        let previous = self.active.replace(id);
+        for thumbnail in &mut self.thumbnails {
+            if thumbnail.source == id || Some(thumbnail.source) == previous {
+                thumbnail.dirty = true;
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: x11rb::protocol::Event) -> Result<()> {
+        use x11rb::protocol::Event as X;
+
+        match event {
+            X::DamageNotify(event) => {
+                self.conn.damage_subtract(event.damage, NONE, NONE)?;
+                if let Some(thumbnail) = self
+                    .thumbnails
+                    .iter_mut()
+                    .find(|t| t.damage == event.damage)
+                {
+                    thumbnail.dirty = true;
+                }
+            }
+            X::Expose(event) if event.count == 0 => {
+                if let Some(thumbnail) = self
+                    .thumbnails
+                    .iter_mut()
+                    .find(|t| t.window == event.window)
+                {
+                    thumbnail.dirty = true;
+                }
+            }
+            X::ConfigureNotify(event) => {
+                let size = (event.width, event.height);
+                if let Some(index) = self
+                    .thumbnails
+                    .iter()
+                    .position(|t| t.source == event.window && t.source_size != size)
+                {
+                    self.thumbnails[index].source_size = size;
+                    self.set_transform(&self.thumbnails[index])?;
+
+                    // The scaled image may no longer cover the old area
+                    let thumbnail = &mut self.thumbnails[index];
+                    self.conn.clear_area(false, thumbnail.window, 0, 0, 0, 0)?;
+                    thumbnail.dirty = true;
+                }
+            }
+            X::ButtonPress(event) if event.detail == 1 => {
+                if let Some(thumbnail) = self.thumbnails.iter().find(|t| t.window == event.event) {
+                    (self.dispatch)(Command::Focus {
+                        character: thumbnail.title.clone(),
+                    });
+                }
+            }
+            X::ButtonPress(event) if event.detail == 2 => {
+                if let Some(thumbnail) = self.thumbnails.iter().find(|t| t.window == event.event) {
+                    let others = self
+                        .thumbnails
+                        .iter()
+                        .filter(|t| t.window != thumbnail.window)
+                        .map(|t| t.rect)
+                        .chain(self.store.load(&self.setup).overlay)
+                        .collect();
+
+                    self.drag = Some(Drag {
+                        window: thumbnail.window,
+                        pointer: (i32::from(event.root_x), i32::from(event.root_y)),
+                        origin: thumbnail.rect,
+                        others,
+                    });
+                }
+            }
+            X::MotionNotify(event) => {
+                if let Some(drag) = &self.drag {
+                    let moved = Rect {
+                        x: drag.origin.x + i32::from(event.root_x) - drag.pointer.0,
+                        y: drag.origin.y + i32::from(event.root_y) - drag.pointer.1,
+                        ..drag.origin
+                    };
+                    let screens: Vec<Rect> = self.monitors.iter().map(|m| m.rect).collect();
+                    let snapped = layout::snap(moved, &drag.others, &screens);
+
+                    if let Some(thumbnail) =
+                        self.thumbnails.iter_mut().find(|t| t.window == drag.window)
+                    {
+                        self.conn.configure_window(
+                            thumbnail.window,
+                            &ConfigureWindowAux::new().x(snapped.x).y(snapped.y),
+                        )?;
+                        thumbnail.rect = snapped;
+                    }
+                }
+            }
+            X::ButtonRelease(event) if event.detail == 2 => {
+                if let Some(drag) = self.drag.take() {
+                    if let Some(thumbnail) = self
+                        .thumbnails
+                        .iter()
+                        .find(|t| t.window == drag.window && t.rect != drag.origin)
+                    {
+                        if let Err(e) =
+                            self.store
+                                .save_character(&self.setup, &thumbnail.title, thumbnail.rect)
+                        {
+                            eprintln!("Failed to save preview layout: {}", e);
+                        }
+                    }
+                }
+            }
+            X::RandrScreenChangeNotify(_) => {
+                self.monitors = layout::detect_monitors(&self.config);
+                let setup = layout::setup_id(&self.monitors);
+                if setup != self.setup {
+                    println!("Monitor setup changed: {}", setup);
+                    self.setup = setup;
+                    self.sync_clients()?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn draw_dirty(&mut self) -> Result<()> {
+        for thumbnail in self.thumbnails.iter_mut().filter(|t| t.dirty) {
+            thumbnail.dirty = false;
+
+            let (width, height) = thumbnail_size(&thumbnail.rect);
+            let (_, x, y, w, h) = fit(thumbnail.source_size, (width, height));
+            self.conn.render_composite(
+                PictOp::SRC,
+                thumbnail.source_picture,
+                NONE,
+                thumbnail.picture,
+                0,
+                0,
+                0,
+                0,
+                x,
+                y,
+                w,
+                h,
+            )?;
+
+            self.conn.image_text8(
+                thumbnail.window,
+                self.label_gc,
+                4,
+                14,
+                &label_bytes(&thumbnail.title),
+            )?;
+
+            if self.active == Some(thumbnail.source) {
+                let inset = (ACTIVE_BORDER / 2) as i16;
+                self.conn.poly_rectangle(
+                    thumbnail.window,
+                    self.border_gc,
+                    &[Rectangle {
+                        x: inset,
+                        y: inset,
+                        width: width.saturating_sub(ACTIVE_BORDER),
+                        height: height.saturating_sub(ACTIVE_BORDER),
+                    }],
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Where the thumbnail for the client at `index` goes when it has no saved
+/// layout: a row starting at `preview_x`/`preview_y`, wrapping onto new rows
+/// at the edge of the display
+fn default_rect(config: &Config, index: usize) -> Rect {
+    let (x, y) = thumbnail_position(config, index);
+    Rect::new(
+        x,
+        y,
+        u32::from(config.preview_width),
+        u32::from(config.preview_height),
+    )
+}
+
+fn thumbnail_position(config: &Config, index: usize) -> (i32, i32) {
+    let step_x = i32::from(config.preview_width) + PREVIEW_GAP;
+    let step_y = i32::from(config.preview_height) + PREVIEW_GAP;
+    let available = config.display_width as i32 - config.preview_x;
+    let columns = (available / step_x).max(1) as usize;
+
+    (
+        config.preview_x + (index % columns) as i32 * step_x,
+        config.preview_y + (index / columns) as i32 * step_y,
+    )
+}
+
+fn thumbnail_size(rect: &Rect) -> (u16, u16) {
+    (
+        rect.width.clamp(1, u32::from(u16::MAX)) as u16,
+        rect.height.clamp(1, u32::from(u16::MAX)) as u16,
+    )
+}
+
+/// Fit `source` inside `target` keeping its aspect ratio. Returns the
+/// source-to-thumbnail scale factor and the centered destination rectangle.
+fn fit(source: (u16, u16), target: (u16, u16)) -> (f64, i16, i16, u16, u16) {
+    let (source_w, source_h) = (f64::from(source.0.max(1)), f64::from(source.1.max(1)));
+    let (target_w, target_h) = (f64::from(target.0.max(1)), f64::from(target.1.max(1)));
+
+    let scale = (source_w / target_w).max(source_h / target_h);
+    let width = (source_w / scale).round() as u16;
+    let height = (source_h / scale).round() as u16;
+    let x = ((target.0 - width.min(target.0)) / 2) as i16;
+    let y = ((target.1 - height.min(target.1)) / 2) as i16;
+
+    (scale, x, y, width, height)
+}
+
+/// XRender 16.16 fixed point
+fn to_fixed(value: f64) -> i32 {
+    (value * 65536.0).round() as i32
+}
+
+/// Label text for the core `fixed` font, which only knows Latin-1
+fn label_bytes(title: &str) -> Vec<u8> {
+    title
+        .chars()
+        .take(u8::MAX as usize)
+        .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+
+    #[test]
+    fn test_fit_keeps_aspect_ratio() {
+        // 16:9 client into a 16:9 thumbnail fills it
+        assert_eq!(fit((1920, 1080), (320, 180)), (6.0, 0, 0, 320, 180));
+
+        // A narrower client is pillarboxed
+        let (scale, x, y, w, h) = fit((1037, 1080), (320, 180));
+        assert_eq!((x, y, h), (73, 0, 180));
+        assert_eq!(w, 173);
+        assert!((scale - 6.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_fit_handles_empty_source() {
+        let (_, _, _, w, h) = fit((0, 0), (320, 180));
+        assert!(w <= 320 && h <= 180);
+    }
+
+    #[test]
+    fn test_thumbnail_position_wraps_at_display_edge() {
+        let config = test_config("");
+
+        assert_eq!(thumbnail_position(&config, 0), (240, 10));
+        assert_eq!(thumbnail_position(&config, 1), (240 + 328, 10));
+
+        // (1920 - 240) / 328 = 5 columns
+        assert_eq!(thumbnail_position(&config, 5), (240, 10 + 188));
+    }
+
+    #[test]
+    fn test_to_fixed() {
+        assert_eq!(to_fixed(1.0), 65536);
+        assert_eq!(to_fixed(0.5), 32768);
+    }
+
+    #[test]
+    fn test_label_bytes_replaces_non_latin1() {
+        assert_eq!(label_bytes("Alt One"), b"Alt One");
+        assert_eq!(label_bytes("Pilot ✈"), b"Pilot ?");
+    }
+}
@@ -0,0 +1,452 @@
+use crate::cycle_state::{CycleMode, CycleScope, SwitchError};
+use crate::window_manager::WindowHandle;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Version of the line-delimited JSON protocol spoken over the daemon socket.
+/// Requests may omit the version; requests from a newer client are rejected.
+/// Version 2 reports window ids as strings (see `WindowHandle`).
+pub const PROTOCOL_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Command {
+    /// Cycle forward, within `group` if given
+    Forward {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        group: Option<String>,
+    },
+    Backward {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        group: Option<String>,
+    },
+    /// Back to the previously focused client, further back on repeated presses
+    Last,
+    /// Switch to client `target` (1-based) of characters.txt, or of `group`
+    Switch {
+        target: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        group: Option<String>,
+    },
+    /// Switch to the client of `character`, by name
+    Focus {
+        character: String,
+    },
+    Refresh,
+    Stack,
+    Reload,
+    /// Ask running overlays to hide or show themselves
+    ToggleOverlay,
+    Status,
+    List,
+    /// Keep the connection open and stream `Event`s after the reply
+    Subscribe,
+    Quit,
+}
+
+impl Command {
+    /// What a cycling command walks and how far, given the configured `mode`,
+    /// so bursts of presses over the same clients can be merged
+    pub fn cycle_steps(&self, mode: CycleMode) -> Option<(CycleScope, isize)> {
+        let scope = |group: &Option<String>| match group {
+            Some(group) => CycleScope::Group(group.clone()),
+            None => CycleScope::All(mode),
+        };
+
+        match self {
+            Command::Forward { group } => Some((scope(group), 1)),
+            Command::Backward { group } => Some((scope(group), -1)),
+            Command::Last => Some((CycleScope::All(CycleMode::Mru), 1)),
+            _ => None,
+        }
+    }
+
+    /// Parse the legacy plain-text form (`forward`, `switch:3`, `forward:dps`,
+    /// `switch:dps:2`, `focus:Alpha`, ...)
+    pub fn from_str(s: &str) -> Option<Self> {
+        let s = s.trim();
+        match s {
+            "forward" => Some(Command::Forward { group: None }),
+            "backward" => Some(Command::Backward { group: None }),
+            "last" => Some(Command::Last),
+            "refresh" => Some(Command::Refresh),
+            "stack" => Some(Command::Stack),
+            "reload" => Some(Command::Reload),
+            "toggle_overlay" => Some(Command::ToggleOverlay),
+            "status" => Some(Command::Status),
+            "list" => Some(Command::List),
+            "subscribe" => Some(Command::Subscribe),
+            "quit" => Some(Command::Quit),
+            _ => {
+                let non_empty = |name: &str| (!name.is_empty()).then(|| name.to_string());
+
+                if let Some(name) = s.strip_prefix("forward:") {
+                    return Some(Command::Forward {
+                        group: Some(non_empty(name)?),
+                    });
+                }
+                if let Some(name) = s.strip_prefix("backward:") {
+                    return Some(Command::Backward {
+                        group: Some(non_empty(name)?),
+                    });
+                }
+                if let Some(name) = s.strip_prefix("focus:") {
+                    return Some(Command::Focus {
+                        character: non_empty(name)?,
+                    });
+                }
+
+                // switch:N or switch:GROUP:N
+                let rest = s.strip_prefix("switch:")?;
+                let (name, num_str) = match rest.rsplit_once(':') {
+                    Some((name, num_str)) => (Some(non_empty(name)?), num_str),
+                    None => (None, rest),
+                };
+                num_str.parse::<usize>().ok().map(|target| Command::Switch {
+                    target,
+                    group: name,
+                })
+            }
+        }
+    }
+}
+
+fn default_version() -> u32 {
+    PROTOCOL_VERSION
+}
+
+/// A single request line, e.g. `{"version":1,"id":7,"cmd":"switch","target":3}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Request {
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+impl Request {
+    pub fn new(command: Command) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            id: None,
+            command,
+        }
+    }
+
+    /// Parse a request line. Lines starting with `{` are JSON, anything else
+    /// is treated as a legacy plain-text command.
+    pub fn parse(line: &str) -> Result<Self, ErrorBody> {
+        let line = line.trim();
+
+        if !line.starts_with('{') {
+            return Command::from_str(line).map(Self::new).ok_or_else(|| {
+                ErrorBody::new(
+                    ErrorCode::UnknownCommand,
+                    format!("Unknown command '{}'", line),
+                )
+            });
+        }
+
+        let request: Request = serde_json::from_str(line)
+            .map_err(|e| ErrorBody::new(ErrorCode::InvalidRequest, e.to_string()))?;
+
+        if request.version > PROTOCOL_VERSION {
+            return Err(ErrorBody::new(
+                ErrorCode::UnsupportedVersion,
+                format!(
+                    "Protocol version {} is not supported (daemon speaks {})",
+                    request.version, PROTOCOL_VERSION
+                ),
+            ));
+        }
+
+        Ok(request)
+    }
+}
+
+/// One tracked client as reported by `status` and `list`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientInfo {
+    /// Position in the daemon's cycle order (0-indexed)
+    pub index: usize,
+    pub id: WindowHandle,
+    pub title: String,
+    pub active: bool,
+    /// Line in characters.txt this character maps to (1-indexed), if any
+    pub slot: Option<usize>,
+}
+
+/// Snapshot of the daemon returned by `status`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusInfo {
+    pub daemon_version: String,
+    pub pid: u32,
+    pub current_index: usize,
+    pub clients: Vec<ClientInfo>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidRequest,
+    UnsupportedVersion,
+    UnknownCommand,
+    OutOfRange,
+    CharacterNotFound,
+    GroupNotFound,
+    CommandFailed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ErrorBody {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Classify an error returned while executing a command
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        let code = match error.downcast_ref::<SwitchError>() {
+            Some(SwitchError::OutOfRange { .. }) => ErrorCode::OutOfRange,
+            Some(SwitchError::CharacterNotFound(_)) => ErrorCode::CharacterNotFound,
+            Some(SwitchError::GroupNotFound(_)) => ErrorCode::GroupNotFound,
+            None => ErrorCode::CommandFailed,
+        };
+
+        Self::new(code, error.to_string())
+    }
+}
+
+/// Reply written for every request, e.g. `{"version":2,"ok":true}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorBody>,
+}
+
+impl Response {
+    pub fn success(id: Option<u64>, data: Option<Value>) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            id,
+            ok: true,
+            data,
+            error: None,
+        }
+    }
+
+    pub fn failure(id: Option<u64>, error: ErrorBody) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            id,
+            ok: false,
+            data: None,
+            error: Some(error),
+        }
+    }
+
+    /// Turn an error reply back into an error for CLI callers
+    pub fn into_result(self) -> anyhow::Result<Option<Value>> {
+        match self.error {
+            Some(error) if !self.ok => anyhow::bail!("{}", error.message),
+            _ => Ok(self.data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_legacy_commands() {
+        assert_eq!(
+            Request::parse("forward\n").unwrap().command,
+            Command::Forward { group: None }
+        );
+        assert_eq!(
+            Request::parse("backward").unwrap().command,
+            Command::Backward { group: None }
+        );
+        assert_eq!(
+            Request::parse("switch:3").unwrap().command,
+            Command::Switch {
+                target: 3,
+                group: None
+            }
+        );
+        assert_eq!(Request::parse("last").unwrap().command, Command::Last);
+        assert_eq!(
+            Request::parse("focus:Alpha Bravo").unwrap().command,
+            Command::Focus {
+                character: "Alpha Bravo".to_string()
+            }
+        );
+        assert_eq!(
+            Request::parse("toggle_overlay").unwrap().command,
+            Command::ToggleOverlay
+        );
+    }
+
+    #[test]
+    fn test_parse_legacy_group_commands() {
+        assert_eq!(
+            Request::parse("forward:dps").unwrap().command,
+            Command::Forward {
+                group: Some("dps".to_string())
+            }
+        );
+        assert_eq!(
+            Request::parse("backward:logi").unwrap().command,
+            Command::Backward {
+                group: Some("logi".to_string())
+            }
+        );
+        assert_eq!(
+            Request::parse("switch:scouts:2").unwrap().command,
+            Command::Switch {
+                target: 2,
+                group: Some("scouts".to_string())
+            }
+        );
+
+        for line in ["forward:", "switch::2", "switch:dps:x", "focus:"] {
+            assert_eq!(
+                Request::parse(line).unwrap_err().code,
+                ErrorCode::UnknownCommand
+            );
+        }
+    }
+
+    #[test]
+    fn test_group_is_optional_in_json() {
+        let request = Request::parse(r#"{"cmd":"forward","group":"dps"}"#).unwrap();
+        assert_eq!(
+            request.command,
+            Command::Forward {
+                group: Some("dps".to_string())
+            }
+        );
+
+        // Requests without a group serialize exactly as before
+        let line = serde_json::to_string(&Request::new(Command::Backward { group: None })).unwrap();
+        assert_eq!(line, r#"{"version":2,"cmd":"backward"}"#);
+    }
+
+    #[test]
+    fn test_cycle_steps_follow_mode() {
+        assert_eq!(
+            Command::Backward { group: None }.cycle_steps(CycleMode::Mru),
+            Some((CycleScope::All(CycleMode::Mru), -1))
+        );
+        // `last` walks the history whatever the configured mode
+        assert_eq!(
+            Command::Last.cycle_steps(CycleMode::Order),
+            Some((CycleScope::All(CycleMode::Mru), 1))
+        );
+        // Groups keep their own order
+        assert_eq!(
+            Command::Forward {
+                group: Some("dps".to_string())
+            }
+            .cycle_steps(CycleMode::Mru),
+            Some((CycleScope::Group("dps".to_string()), 1))
+        );
+        assert_eq!(Command::Status.cycle_steps(CycleMode::Order), None);
+    }
+
+    #[test]
+    fn test_parse_legacy_queries() {
+        assert_eq!(Request::parse("status").unwrap().command, Command::Status);
+        assert_eq!(Request::parse("list").unwrap().command, Command::List);
+        assert_eq!(
+            Request::parse("subscribe").unwrap().command,
+            Command::Subscribe
+        );
+    }
+
+    #[test]
+    fn test_parse_legacy_unknown_command() {
+        let error = Request::parse("switch:abc").unwrap_err();
+        assert_eq!(error.code, ErrorCode::UnknownCommand);
+    }
+
+    #[test]
+    fn test_parse_json_request() {
+        let request = Request::parse(r#"{"version":1,"id":7,"cmd":"switch","target":2}"#).unwrap();
+        assert_eq!(request.id, Some(7));
+        assert_eq!(
+            request.command,
+            Command::Switch {
+                target: 2,
+                group: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_json_request_without_version() {
+        let request = Request::parse(r#"{"cmd":"forward"}"#).unwrap();
+        assert_eq!(request.version, PROTOCOL_VERSION);
+        assert_eq!(request.command, Command::Forward { group: None });
+    }
+
+    #[test]
+    fn test_parse_json_rejects_newer_version() {
+        let line = format!(r#"{{"version":{},"cmd":"forward"}}"#, PROTOCOL_VERSION + 1);
+        let error = Request::parse(&line).unwrap_err();
+        assert_eq!(error.code, ErrorCode::UnsupportedVersion);
+    }
+
+    #[test]
+    fn test_parse_json_invalid_request() {
+        let error = Request::parse(r#"{"cmd":"teleport"}"#).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+    }
+
+    #[test]
+    fn test_request_round_trip() {
+        let request = Request::new(Command::Switch {
+            target: 4,
+            group: Some("dps".to_string()),
+        });
+        let line = serde_json::to_string(&request).unwrap();
+        assert_eq!(Request::parse(&line).unwrap(), request);
+    }
+
+    #[test]
+    fn test_error_classification() {
+        let error: anyhow::Error = SwitchError::CharacterNotFound("Alpha".to_string()).into();
+        let body = ErrorBody::from_error(&error);
+        assert_eq!(body.code, ErrorCode::CharacterNotFound);
+        assert_eq!(
+            body.message,
+            "Character 'Alpha' not found in active windows"
+        );
+
+        let body = ErrorBody::from_error(&anyhow::anyhow!("compositor went away"));
+        assert_eq!(body.code, ErrorCode::CommandFailed);
+    }
+
+    #[test]
+    fn test_failure_into_result() {
+        let response = Response::failure(None, ErrorBody::new(ErrorCode::OutOfRange, "nope"));
+        assert!(response.into_result().is_err());
+        assert!(Response::success(None, None).into_result().is_ok());
+    }
+}
@@ -0,0 +1,194 @@
+use anyhow::{Context, Result};
+use nix::unistd::getuid;
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::io::ErrorKind;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+/// Directory holding the socket, index file and locks for this user and display:
+/// `$XDG_RUNTIME_DIR/nicotine/<display>/`, or `/tmp/nicotine-<uid>/<display>/`
+/// when no runtime dir is set
+pub fn runtime_dir() -> PathBuf {
+    runtime_base().join(display_id(
+        env::var("WAYLAND_DISPLAY").ok().as_deref(),
+        env::var("DISPLAY").ok().as_deref(),
+    ))
+}
+
+/// Per-user directory the display dirs live in
+fn runtime_base() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("nicotine"),
+        _ => PathBuf::from(format!("/tmp/nicotine-{}", getuid())),
+    }
+}
+
+pub fn socket_path() -> PathBuf {
+    runtime_dir().join("nicotine.sock")
+}
+
+pub fn index_file() -> PathBuf {
+    runtime_dir().join("index")
+}
+
+pub fn cycle_lock() -> PathBuf {
+    runtime_dir().join("cycle.lock")
+}
+
+pub fn pid_file() -> PathBuf {
+    runtime_dir().join("daemon.pid")
+}
+
+/// Create the runtime dir with owner-only permissions, refusing to use one
+/// that somebody else could have set up: in `/tmp` any user can create
+/// `nicotine-<uid>` first
+pub fn ensure_runtime_dir() -> Result<PathBuf> {
+    let dir = runtime_dir();
+    create_private_dir(&runtime_base())?;
+    create_private_dir(&dir)?;
+    Ok(dir)
+}
+
+/// Create `dir` as 0700, or check that the existing one is a real directory
+/// of ours that nobody else can get into
+fn create_private_dir(dir: &Path) -> Result<()> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("Failed to create runtime dir {}", dir.display()))
+        }
+    }
+
+    // Not following symlinks, which could point anywhere
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() {
+        anyhow::bail!("Runtime dir {} is not a directory", dir.display());
+    }
+    if metadata.uid() != getuid().as_raw() {
+        anyhow::bail!(
+            "Runtime dir {} is owned by another user (uid {})",
+            dir.display(),
+            metadata.uid()
+        );
+    }
+    if metadata.mode() & 0o077 != 0 {
+        anyhow::bail!(
+            "Runtime dir {} is open to other users (mode {:o})",
+            dir.display(),
+            metadata.mode() & 0o777
+        );
+    }
+
+    Ok(())
+}
+
+/// Remove a runtime file, but only if it belongs to the current user
+pub fn remove_owned(path: &Path) -> Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(()), // Nothing to remove
+    };
+
+    if metadata.uid() != getuid().as_raw() {
+        anyhow::bail!(
+            "Not removing {}: owned by uid {}",
+            path.display(),
+            metadata.uid()
+        );
+    }
+
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+/// Directory name for a display, e.g. `wayland-1` or `x11-0`
+fn display_id(wayland_display: Option<&str>, x11_display: Option<&str>) -> String {
+    let id = if let Some(wayland) = wayland_display.filter(|d| !d.is_empty()) {
+        // WAYLAND_DISPLAY may be an absolute socket path
+        Path::new(wayland)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| wayland.to_string())
+    } else if let Some(x11) = x11_display.filter(|d| !d.is_empty()) {
+        // host:display.screen -> x11-[host-]display
+        let (host, rest) = x11.rsplit_once(':').unwrap_or(("", x11));
+        let display = rest.split('.').next().unwrap_or(rest);
+        if host.is_empty() {
+            format!("x11-{}", display)
+        } else {
+            format!("x11-{}-{}", host, display)
+        }
+    } else {
+        "default".to_string()
+    };
+
+    id.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_display_id_x11() {
+        assert_eq!(display_id(None, Some(":0")), "x11-0");
+        assert_eq!(display_id(None, Some(":1.0")), "x11-1");
+        assert_eq!(display_id(None, Some("localhost:10.0")), "x11-localhost-10");
+    }
+
+    #[test]
+    fn test_display_id_wayland_takes_priority() {
+        assert_eq!(display_id(Some("wayland-1"), Some(":0")), "wayland-1");
+        assert_eq!(
+            display_id(Some("/run/user/1000/wayland-0"), None),
+            "wayland-0"
+        );
+    }
+
+    #[test]
+    fn test_display_id_fallback() {
+        assert_eq!(display_id(None, None), "default");
+        assert_eq!(display_id(Some(""), Some("")), "default");
+    }
+
+    #[test]
+    fn test_private_dir_rejects_shared_and_symlinked() {
+        let base = env::temp_dir().join(format!("nicotine-runtime-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        fs::create_dir(&base).unwrap();
+
+        let private = base.join("private");
+        create_private_dir(&private).unwrap();
+        create_private_dir(&private).unwrap();
+
+        // Pre-created by someone for everybody to use
+        let shared = base.join("shared");
+        fs::create_dir(&shared).unwrap();
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(create_private_dir(&shared).is_err());
+
+        // Even pointing at a private dir of ours, a symlink could be redirected
+        let link = base.join("link");
+        std::os::unix::fs::symlink(&private, &link).unwrap();
+        assert!(create_private_dir(&link).is_err());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_display_id_is_sanitized() {
+        assert_eq!(display_id(None, Some("my/host:0")), "x11-my_host-0");
+    }
+}
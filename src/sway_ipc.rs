@@ -0,0 +1,249 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 6] = b"i3-ipc";
+
+/// i3-ipc message types used by nicotine
+pub const RUN_COMMAND: u32 = 0;
+pub const SUBSCRIBE: u32 = 2;
+pub const GET_TREE: u32 = 4;
+
+/// Event replies have the high bit set; `window` is event 3
+pub const EVENT_MASK: u32 = 0x8000_0000;
+pub const WINDOW_EVENT: u32 = EVENT_MASK | 3;
+
+/// Write one i3-ipc frame: magic, payload length, type (both native-endian u32), payload
+pub fn write_message(writer: &mut impl Write, message_type: u32, payload: &[u8]) -> Result<()> {
+    let mut frame = Vec::with_capacity(MAGIC.len() + 8 + payload.len());
+    frame.extend_from_slice(MAGIC);
+    frame.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    frame.extend_from_slice(&message_type.to_ne_bytes());
+    frame.extend_from_slice(payload);
+
+    writer.write_all(&frame)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read one i3-ipc frame, returning its type and payload
+pub fn read_message(reader: &mut impl Read) -> Result<(u32, Vec<u8>)> {
+    let mut header = [0u8; 14];
+    reader.read_exact(&mut header)?;
+
+    if &header[..6] != MAGIC {
+        anyhow::bail!("Invalid i3-ipc magic: {:?}", &header[..6]);
+    }
+
+    let length = u32::from_ne_bytes(header[6..10].try_into().unwrap()) as usize;
+    let message_type = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+
+    let mut payload = vec![0u8; length];
+    reader.read_exact(&mut payload)?;
+
+    Ok((message_type, payload))
+}
+
+/// A request/reply connection to sway's IPC socket, kept open between calls
+pub struct SwayIpc {
+    stream: UnixStream,
+}
+
+impl SwayIpc {
+    pub fn connect(path: &Path) -> Result<Self> {
+        let stream = UnixStream::connect(path)
+            .with_context(|| format!("Failed to connect to sway socket {}", path.display()))?;
+        Ok(Self { stream })
+    }
+
+    /// `$SWAYSOCK`, or `$I3SOCK` as sway sets both
+    pub fn socket_path() -> Result<PathBuf> {
+        std::env::var_os("SWAYSOCK")
+            .or_else(|| std::env::var_os("I3SOCK"))
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .context("SWAYSOCK not set. Make sure you're running Sway")
+    }
+
+    pub fn request(&mut self, message_type: u32, payload: &str) -> Result<Value> {
+        write_message(&mut self.stream, message_type, payload.as_bytes())?;
+
+        let (reply_type, reply) = read_message(&mut self.stream)?;
+        if reply_type != message_type {
+            anyhow::bail!(
+                "Unexpected sway reply type {} (expected {})",
+                reply_type,
+                message_type
+            );
+        }
+
+        serde_json::from_slice(&reply).context("Failed to parse sway reply")
+    }
+
+    pub fn get_tree(&mut self) -> Result<Value> {
+        self.request(GET_TREE, "")
+    }
+
+    /// Run one or more `;`-separated commands in a single round trip
+    pub fn run_command(&mut self, command: &str) -> Result<()> {
+        let results = self.request(RUN_COMMAND, command)?;
+
+        let failures: Vec<&str> = results
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|result| !result["success"].as_bool().unwrap_or(false))
+            .map(|result| result["error"].as_str().unwrap_or("unknown error"))
+            .collect();
+
+        if !failures.is_empty() {
+            anyhow::bail!("sway command failed: {}", failures.join("; "));
+        }
+
+        Ok(())
+    }
+
+    /// Subscribe to `events` (e.g. `["window"]`) on this connection, turning it
+    /// into an event stream
+    pub fn subscribe(mut self, events: &[&str]) -> Result<SwayEvents> {
+        let reply = self.request(SUBSCRIBE, &serde_json::to_string(events)?)?;
+        if !reply["success"].as_bool().unwrap_or(false) {
+            anyhow::bail!("sway refused subscription to {:?}", events);
+        }
+
+        Ok(SwayEvents {
+            stream: self.stream,
+        })
+    }
+}
+
+/// Events from a subscribed connection, as `(event type, payload)`
+pub struct SwayEvents {
+    stream: UnixStream,
+}
+
+impl Iterator for SwayEvents {
+    type Item = Result<(u32, Value)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (event_type, payload) = match read_message(&mut self.stream) {
+            Ok(message) => message,
+            Err(e) => return Some(Err(e)),
+        };
+
+        Some(
+            serde_json::from_slice(&payload)
+                .map(|event| (event_type, event))
+                .context("Failed to parse sway event"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    /// A one-connection fake sway that answers each request with the next
+    /// canned reply (echoing the request's type), then sends `events`
+    fn fake_sway(name: &str, replies: Vec<&'static str>, events: Vec<&'static str>) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "nicotine-sway-{}-{}.sock",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            for reply in replies {
+                let (message_type, _) = read_message(&mut stream).unwrap();
+                write_message(&mut stream, message_type, reply.as_bytes()).unwrap();
+            }
+            for event in events {
+                write_message(&mut stream, WINDOW_EVENT, event.as_bytes()).unwrap();
+            }
+        });
+
+        path
+    }
+
+    #[test]
+    fn test_frame_round_trip() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, RUN_COMMAND, b"focus").unwrap();
+
+        assert_eq!(&buffer[..6], b"i3-ipc");
+        assert_eq!(buffer.len(), 14 + 5);
+
+        let (message_type, payload) = read_message(&mut buffer.as_slice()).unwrap();
+        assert_eq!(message_type, RUN_COMMAND);
+        assert_eq!(payload, b"focus");
+    }
+
+    #[test]
+    fn test_read_rejects_bad_magic() {
+        let frame = b"i4-ipc\0\0\0\0\0\0\0\0";
+        assert!(read_message(&mut frame.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_get_tree_over_socket() {
+        let path = fake_sway("tree", vec![r#"{"type":"root","nodes":[]}"#], vec![]);
+
+        let mut ipc = SwayIpc::connect(&path).unwrap();
+        let tree = ipc.get_tree().unwrap();
+        assert_eq!(tree["type"], "root");
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_run_command_reports_failures() {
+        let path = fake_sway(
+            "command",
+            vec![
+                r#"[{"success":true},{"success":true}]"#,
+                r#"[{"success":true},{"success":false,"error":"No matching node"}]"#,
+            ],
+            vec![],
+        );
+
+        let mut ipc = SwayIpc::connect(&path).unwrap();
+        ipc.run_command("[con_id=1] focus; [con_id=2] focus")
+            .unwrap();
+        let error = ipc
+            .run_command("[con_id=1] focus; [con_id=3] focus")
+            .unwrap_err();
+        assert!(error.to_string().contains("No matching node"));
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_subscribe_streams_events() {
+        let path = fake_sway(
+            "events",
+            vec![r#"{"success":true}"#],
+            vec![r#"{"change":"focus","container":{"id":7,"name":"EVE - Alpha"}}"#],
+        );
+
+        let mut events = SwayIpc::connect(&path)
+            .unwrap()
+            .subscribe(&["window"])
+            .unwrap();
+
+        let (event_type, event) = events.next().unwrap().unwrap();
+        assert_eq!(event_type, WINDOW_EVENT);
+        assert_eq!(event["change"], "focus");
+        assert_eq!(event["container"]["id"], 7);
+
+        // The fake server hangs up after its last event
+        assert!(events.next().unwrap().is_err());
+
+        let _ = std::fs::remove_file(path);
+    }
+}
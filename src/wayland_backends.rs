@@ -1,11 +1,172 @@
 use crate::config::Config;
-use crate::window_manager::{EveWindow, WindowManager};
+use crate::hyprland_ipc::{self, HyprlandEvents};
+use crate::kwin_scripting::{js_string, KWinScripts};
+use crate::runtime;
+use crate::sway_ipc::{self, SwayIpc};
+use crate::window_manager::{Capabilities, EveWindow, WindowEvent, WindowHandle, WindowManager};
+use crate::wlr_toplevel::ForeignToplevels;
 use anyhow::{Context, Result};
 use serde_json::Value;
+use std::path::PathBuf;
 use std::process::Command;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
 
 // ============================================================================
-// KDE Plasma / KWin Backend (via wmctrl through XWayland)
+// KDE Plasma / KWin Backend (via KWin scripting over D-Bus)
+// ============================================================================
+
+/// Drives KWin from the inside with scripts, so native Wayland clients
+/// are covered and no external tools are needed
+pub struct KWinScriptManager {
+    scripts: KWinScripts,
+}
+
+impl KWinScriptManager {
+    pub fn new() -> Result<Self> {
+        let script_dir = runtime::ensure_runtime_dir()?.join("kwin");
+        Ok(Self {
+            scripts: KWinScripts::connect(&script_dir)?,
+        })
+    }
+
+    fn get_all_windows(&self) -> Result<Vec<(WindowHandle, String)>> {
+        let reply = self.scripts.run(
+            "reply(windows().filter(w => w.normalWindow).map(w => ({ id: windowId(w), title: w.caption })));",
+        )?;
+
+        let windows = reply
+            .as_array()
+            .context("Failed to parse KWin window list")?
+            .iter()
+            .filter_map(|window| {
+                let id = window["id"].as_str()?;
+                let title = window["title"].as_str()?;
+                Some((WindowHandle::Name(id.to_string()), title.to_string()))
+            })
+            .collect();
+
+        Ok(windows)
+    }
+
+    /// Run `action` on window `w`, failing if the window is gone
+    fn with_window(&self, window_id: &WindowHandle, action: &str) -> Result<()> {
+        let body = format!(
+            "const w = findWindow({}); if (w) {{ {} }} reply(!!w);",
+            js_string(&window_id.to_string()),
+            action
+        );
+
+        if self.scripts.run(&body)? != Value::Bool(true) {
+            anyhow::bail!("Window {} not found", window_id);
+        }
+        Ok(())
+    }
+}
+
+impl WindowManager for KWinScriptManager {
+    fn name(&self) -> &'static str {
+        "KWin"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            move_windows: true,
+            resize_windows: true,
+            minimize: true,
+            stacking: true,
+            events: true,
+            thumbnails: false,
+            overlay_dragging: true,
+        }
+    }
+
+    fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
+        let eve_windows = self
+            .get_all_windows()?
+            .into_iter()
+            .filter(|(_, title)| title.starts_with("EVE - ") && !title.contains("Launcher"))
+            .map(|(id, title)| EveWindow {
+                id,
+                title: title.trim_start_matches("EVE - ").to_string(),
+            })
+            .collect();
+
+        Ok(eve_windows)
+    }
+
+    fn activate_window(&self, window_id: &WindowHandle) -> Result<()> {
+        self.with_window(window_id, "activate(w);")
+            .context("Failed to activate window")
+    }
+
+    fn stack_windows(&self, windows: &[EveWindow], config: &Config) -> Result<()> {
+        let x = ((config.display_width - config.eve_width) / 2) as i32;
+        let y = 0;
+        let width = config.eve_width;
+        let height = config.display_height - config.panel_height;
+
+        let ids: Vec<String> = windows.iter().map(|w| w.id.to_string()).collect();
+        let body = format!(
+            "for (const id of {}) {{ const w = findWindow(id); \
+             if (w) w.frameGeometry = {{ x: {}, y: {}, width: {}, height: {} }}; }} reply(true);",
+            serde_json::to_string(&ids)?,
+            x,
+            y,
+            width,
+            height
+        );
+
+        self.scripts.run(&body)?;
+        Ok(())
+    }
+
+    fn get_active_window(&self) -> Result<WindowHandle> {
+        let reply = self
+            .scripts
+            .run("const w = activeWindow(); reply(w ? windowId(w) : null);")?;
+
+        match reply.as_str() {
+            Some(id) => Ok(WindowHandle::Name(id.to_string())),
+            None => anyhow::bail!("No active window found"),
+        }
+    }
+
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowHandle>> {
+        Ok(self
+            .get_all_windows()?
+            .into_iter()
+            .find(|(_, window_title)| window_title == title)
+            .map(|(id, _)| id))
+    }
+
+    fn move_window(&self, window_id: &WindowHandle, x: i32, y: i32) -> Result<()> {
+        self.with_window(
+            window_id,
+            &format!(
+                "w.frameGeometry = {{ x: {}, y: {}, width: w.frameGeometry.width, height: w.frameGeometry.height }};",
+                x, y
+            ),
+        )
+    }
+
+    fn minimize_window(&self, window_id: &WindowHandle) -> Result<()> {
+        self.with_window(window_id, "w.minimized = true;")
+            .context("Failed to minimize window")
+    }
+
+    fn restore_window(&self, window_id: &WindowHandle) -> Result<()> {
+        self.with_window(window_id, "w.minimized = false;")
+            .context("Failed to restore window")
+    }
+
+    fn watch_windows(&self) -> Result<Option<Receiver<WindowEvent>>> {
+        self.scripts.watch().map(Some)
+    }
+}
+
+// ============================================================================
+// KDE Plasma / KWin Fallback (via wmctrl through XWayland)
 // ============================================================================
 
 pub struct KWinManager;
@@ -45,6 +206,23 @@ impl KWinManager {
         Ok(windows)
     }
 
+    /// Parse a wmctrl window id (e.g. "0x06e00008")
+    fn parse_id(id_str: &str) -> Option<WindowHandle> {
+        let id = match id_str.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+            None => id_str.parse().ok()?,
+        };
+        (id != 0).then_some(WindowHandle::Id(id))
+    }
+
+    /// Format a handle the way wmctrl prints window ids
+    fn hex_id(window_id: &WindowHandle) -> Result<String> {
+        window_id
+            .as_u64()
+            .map(|id| format!("0x{:08x}", id))
+            .with_context(|| format!("Not a wmctrl window id: {}", window_id))
+    }
+
     fn get_window_title_by_id(&self, hex_id: &str) -> Option<String> {
         let output = Command::new("wmctrl").arg("-l").output().ok()?;
         if !output.status.success() {
@@ -65,20 +243,29 @@ impl KWinManager {
 }
 
 impl WindowManager for KWinManager {
+    fn name(&self) -> &'static str {
+        "KWin (wmctrl)"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            move_windows: false,
+            resize_windows: true,
+            minimize: true,
+            stacking: true,
+            events: false,
+            thumbnails: false,
+            overlay_dragging: false,
+        }
+    }
+
     fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
         let windows = self.get_all_windows()?;
         let mut eve_windows = Vec::new();
 
         for (id_str, title) in windows {
             if title.starts_with("EVE - ") && !title.contains("Launcher") {
-                // Parse hex window ID (e.g., "0x06e00008") to u32
-                let id = if let Some(hex) = id_str.strip_prefix("0x") {
-                    u32::from_str_radix(hex, 16).unwrap_or(0)
-                } else {
-                    id_str.parse::<u32>().unwrap_or(0)
-                };
-
-                if id != 0 {
+                if let Some(id) = Self::parse_id(&id_str) {
                     eve_windows.push(EveWindow {
                         id,
                         title: title.trim_start_matches("EVE - ").to_string(),
@@ -90,8 +277,8 @@ impl WindowManager for KWinManager {
         Ok(eve_windows)
     }
 
-    fn activate_window(&self, window_id: u32) -> Result<()> {
-        let hex_id = format!("0x{:08x}", window_id);
+    fn activate_window(&self, window_id: &WindowHandle) -> Result<()> {
+        let hex_id = Self::hex_id(window_id)?;
 
         if let Some(title) = self.get_window_title_by_id(&hex_id) {
             if Command::new("kdotool")
@@ -119,8 +306,7 @@ impl WindowManager for KWinManager {
         let height = config.display_height - config.panel_height;
 
         for window in windows {
-            // Convert u32 to hex format for wmctrl
-            let hex_id = format!("0x{:08x}", window.id);
+            let hex_id = Self::hex_id(&window.id)?;
 
             // Move and resize window using wmctrl
             Command::new("wmctrl")
@@ -135,7 +321,7 @@ impl WindowManager for KWinManager {
         Ok(())
     }
 
-    fn get_active_window(&self) -> Result<u32> {
+    fn get_active_window(&self) -> Result<WindowHandle> {
         // Use xdotool to get active window (works through XWayland)
         let output = Command::new("xdotool")
             .arg("getactivewindow")
@@ -144,25 +330,18 @@ impl WindowManager for KWinManager {
 
         let window_id = String::from_utf8_lossy(&output.stdout)
             .trim()
-            .parse::<u32>()
+            .parse::<u64>()
             .context("Failed to parse active window ID")?;
 
-        Ok(window_id)
+        Ok(WindowHandle::Id(window_id))
     }
 
-    fn find_window_by_title(&self, title: &str) -> Result<Option<u32>> {
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowHandle>> {
         let windows = self.get_all_windows()?;
 
         for (id_str, window_title) in windows {
             if window_title == title {
-                // Parse hex window ID (e.g., "0x06e00008") to u32
-                let id = if let Some(hex) = id_str.strip_prefix("0x") {
-                    u32::from_str_radix(hex, 16).unwrap_or(0)
-                } else {
-                    id_str.parse::<u32>().unwrap_or(0)
-                };
-
-                if id != 0 {
+                if let Some(id) = Self::parse_id(&id_str) {
                     return Ok(Some(id));
                 }
             }
@@ -171,8 +350,8 @@ impl WindowManager for KWinManager {
         Ok(None)
     }
 
-    fn minimize_window(&self, window_id: u32) -> Result<()> {
-        let hex_id = format!("0x{:08x}", window_id);
+    fn minimize_window(&self, window_id: &WindowHandle) -> Result<()> {
+        let hex_id = Self::hex_id(window_id)?;
         Command::new("xdotool")
             .args(["windowminimize", &hex_id])
             .output()
@@ -180,8 +359,8 @@ impl WindowManager for KWinManager {
         Ok(())
     }
 
-    fn restore_window(&self, window_id: u32) -> Result<()> {
-        let hex_id = format!("0x{:08x}", window_id);
+    fn restore_window(&self, window_id: &WindowHandle) -> Result<()> {
+        let hex_id = Self::hex_id(window_id)?;
         // wmctrl -i -a activates and restores from minimized state
         Command::new("wmctrl")
             .args(["-i", "-a", &hex_id])
@@ -192,38 +371,49 @@ impl WindowManager for KWinManager {
 }
 
 // ============================================================================
-// Sway Backend (via swaymsg)
+// Sway Backend (via the i3-ipc socket)
 // ============================================================================
 
-pub struct SwayManager;
+pub struct SwayManager {
+    socket: PathBuf,
+    ipc: Mutex<SwayIpc>,
+    /// Focused container, kept current by `watch_windows` once it is running
+    focused: Arc<Mutex<Option<WindowHandle>>>,
+}
 
 impl SwayManager {
     pub fn new() -> Result<Self> {
-        // Verify swaymsg is available
-        Command::new("swaymsg")
-            .arg("--version")
-            .output()
-            .context("swaymsg not found. Make sure you're running Sway")?;
-
-        Ok(Self)
+        let socket = SwayIpc::socket_path()?;
+        let ipc = SwayIpc::connect(&socket)?;
+
+        Ok(Self {
+            socket,
+            ipc: Mutex::new(ipc),
+            focused: Arc::new(Mutex::new(None)),
+        })
     }
 
-    fn get_all_windows(&self) -> Result<Vec<Value>> {
-        let output = Command::new("swaymsg")
-            .arg("-t")
-            .arg("get_tree")
-            .output()
-            .context("Failed to execute swaymsg")?;
+    /// Send a request on the shared connection, reconnecting once if sway
+    /// dropped it
+    fn with_ipc<T>(&self, request: impl Fn(&mut SwayIpc) -> Result<T>) -> Result<T> {
+        let mut ipc = self.ipc.lock().unwrap();
 
-        if !output.status.success() {
-            anyhow::bail!(
-                "swaymsg failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            );
+        match request(&mut ipc) {
+            Ok(value) => Ok(value),
+            Err(e) if e.downcast_ref::<std::io::Error>().is_some() => {
+                *ipc = SwayIpc::connect(&self.socket)?;
+                request(&mut ipc)
+            }
+            Err(e) => Err(e),
         }
+    }
+
+    fn run_command(&self, command: &str) -> Result<()> {
+        self.with_ipc(|ipc| ipc.run_command(command))
+    }
 
-        let tree: Value =
-            serde_json::from_slice(&output.stdout).context("Failed to parse swaymsg output")?;
+    fn get_all_windows(&self) -> Result<Vec<Value>> {
+        let tree = self.with_ipc(|ipc| ipc.get_tree())?;
 
         let mut windows = Vec::new();
         Self::extract_windows(&tree, &mut windows);
@@ -266,12 +456,40 @@ impl SwayManager {
             .map(|s| s.to_string())
     }
 
-    fn get_window_id(window: &Value) -> Option<u32> {
-        window.get("id").and_then(|i| i.as_u64()).map(|i| i as u32)
+    fn get_window_id(window: &Value) -> Option<WindowHandle> {
+        window
+            .get("id")
+            .and_then(|i| i.as_u64())
+            .map(WindowHandle::Id)
+    }
+
+    /// `[con_id=N]` criteria for a window
+    fn criteria(window_id: &WindowHandle) -> Result<String> {
+        window_id
+            .as_u64()
+            .map(|id| format!("[con_id={}]", id))
+            .with_context(|| format!("Not a sway container id: {}", window_id))
     }
 }
 
 impl WindowManager for SwayManager {
+    fn name(&self) -> &'static str {
+        "Sway"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // "Minimizing" would mean the scratchpad, which hides clients for good
+        Capabilities {
+            move_windows: false,
+            resize_windows: true,
+            minimize: false,
+            stacking: true,
+            events: true,
+            thumbnails: false,
+            overlay_dragging: false,
+        }
+    }
+
     fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
         let windows = self.get_all_windows()?;
         let mut eve_windows = Vec::new();
@@ -292,20 +510,9 @@ impl WindowManager for SwayManager {
         Ok(eve_windows)
     }
 
-    fn activate_window(&self, window_id: u32) -> Result<()> {
-        let output = Command::new("swaymsg")
-            .arg(format!("[con_id={}] focus", window_id))
-            .output()
-            .context("Failed to activate window")?;
-
-        if !output.status.success() {
-            anyhow::bail!(
-                "Failed to activate window: {}",
-                String::from_utf8_lossy(&output.stderr)
-            );
-        }
-
-        Ok(())
+    fn activate_window(&self, window_id: &WindowHandle) -> Result<()> {
+        self.run_command(&format!("{} focus", Self::criteria(window_id)?))
+            .context("Failed to activate window")
     }
 
     fn stack_windows(&self, windows: &[EveWindow], config: &Config) -> Result<()> {
@@ -314,28 +521,35 @@ impl WindowManager for SwayManager {
         let width = config.eve_width as i32;
         let height = (config.display_height - config.panel_height) as i32;
 
-        for window in windows {
-            // Sway uses floating mode for positioning
-            Command::new("swaymsg")
-                .arg(format!("[con_id={}] floating enable", window.id))
-                .output()?;
-
-            Command::new("swaymsg")
-                .arg(format!("[con_id={}] move position {} {}", window.id, x, y))
-                .output()?;
-
-            Command::new("swaymsg")
-                .arg(format!(
-                    "[con_id={}] resize set {} {}",
-                    window.id, width, height
+        // Sway uses floating mode for positioning. All windows go in one
+        // request: `,` chains commands on a window, `;` separates windows
+        let commands = windows
+            .iter()
+            .map(|window| {
+                Ok(format!(
+                    "{} floating enable, move position {} {}, resize set {} {}",
+                    Self::criteria(&window.id)?,
+                    x,
+                    y,
+                    width,
+                    height
                 ))
-                .output()?;
+            })
+            .collect::<Result<Vec<String>>>()?;
+
+        if commands.is_empty() {
+            return Ok(());
         }
 
-        Ok(())
+        self.run_command(&commands.join("; "))
     }
 
-    fn get_active_window(&self) -> Result<u32> {
+    fn get_active_window(&self) -> Result<WindowHandle> {
+        // Kept up to date by focus events while the daemon is watching
+        if let Some(id) = self.focused.lock().unwrap().clone() {
+            return Ok(id);
+        }
+
         let windows = self.get_all_windows()?;
 
         for window in windows {
@@ -351,7 +565,7 @@ impl WindowManager for SwayManager {
         anyhow::bail!("No active window found")
     }
 
-    fn find_window_by_title(&self, title: &str) -> Result<Option<u32>> {
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowHandle>> {
         let windows = self.get_all_windows()?;
 
         for window in windows {
@@ -367,63 +581,105 @@ impl WindowManager for SwayManager {
         Ok(None)
     }
 
-    fn minimize_window(&self, window_id: u32) -> Result<()> {
-        Command::new("swaymsg")
-            .arg(format!("[con_id={}] move scratchpad", window_id))
-            .output()
-            .context("Failed to minimize window")?;
-        Ok(())
+    fn minimize_window(&self, window_id: &WindowHandle) -> Result<()> {
+        self.run_command(&format!("{} move scratchpad", Self::criteria(window_id)?))
+            .context("Failed to minimize window")
     }
 
-    fn restore_window(&self, window_id: u32) -> Result<()> {
+    fn restore_window(&self, window_id: &WindowHandle) -> Result<()> {
         // Show from scratchpad restores it
-        Command::new("swaymsg")
-            .arg(format!("[con_id={}] scratchpad show", window_id))
-            .output()
-            .context("Failed to restore window")?;
-        Ok(())
+        self.run_command(&format!("{} scratchpad show", Self::criteria(window_id)?))
+            .context("Failed to restore window")
+    }
+
+    fn watch_windows(&self) -> Result<Option<Receiver<WindowEvent>>> {
+        let events = SwayIpc::connect(&self.socket)?.subscribe(&["window"])?;
+        let focused = Arc::clone(&self.focused);
+        let (tx, rx) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for event in events {
+                let (event_type, event) = match event {
+                    Ok(event) => event,
+                    Err(_) => break,
+                };
+                if event_type != sway_ipc::WINDOW_EVENT {
+                    continue;
+                }
+
+                let container = &event["container"];
+                let id = Self::get_window_id(container);
+                let is_eve = container["name"]
+                    .as_str()
+                    .is_some_and(|name| name.starts_with("EVE"));
+
+                let change = match event["change"].as_str() {
+                    Some("focus") => id.map(|id| {
+                        *focused.lock().unwrap() = Some(id.clone());
+                        WindowEvent::ActiveChanged(id)
+                    }),
+                    // Logging in renames "EVE" to "EVE - <character>"
+                    Some("new" | "close" | "title") if is_eve => Some(WindowEvent::WindowsChanged),
+                    _ => None,
+                };
+
+                if let Some(change) = change {
+                    if tx.send(change).is_err() {
+                        break;
+                    }
+                }
+            }
+
+            // Stop answering from a cache that no longer updates
+            *focused.lock().unwrap() = None;
+        });
+
+        Ok(Some(rx))
     }
 }
 
 // ============================================================================
-// Hyprland Backend (via hyprctl)
+// Hyprland Backend (via the Hyprland sockets)
 // ============================================================================
 
-pub struct HyprlandManager;
+pub struct HyprlandManager {
+    dir: PathBuf,
+}
 
 impl HyprlandManager {
     pub fn new() -> Result<Self> {
-        // Verify hyprctl is available
-        Command::new("hyprctl")
-            .arg("version")
-            .output()
-            .context("hyprctl not found. Make sure you're running Hyprland")?;
-
-        Ok(Self)
+        Ok(Self {
+            dir: hyprland_ipc::socket_dir()?,
+        })
     }
 
     fn get_all_windows(&self) -> Result<Vec<Value>> {
-        let output = Command::new("hyprctl")
-            .arg("clients")
-            .arg("-j")
-            .output()
-            .context("Failed to execute hyprctl")?;
-
-        if !output.status.success() {
-            anyhow::bail!(
-                "hyprctl failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            );
-        }
-
+        let clients = hyprland_ipc::query(&self.dir, "clients")?;
         let windows: Vec<Value> =
-            serde_json::from_slice(&output.stdout).context("Failed to parse hyprctl output")?;
+            serde_json::from_value(clients).context("Failed to parse Hyprland clients")?;
 
         Ok(windows)
     }
 }
 
 impl WindowManager for HyprlandManager {
+    fn name(&self) -> &'static str {
+        "Hyprland"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // "Minimizing" would mean a special workspace
+        Capabilities {
+            move_windows: false,
+            resize_windows: true,
+            minimize: false,
+            stacking: true,
+            events: true,
+            thumbnails: false,
+            overlay_dragging: false,
+        }
+    }
+
     fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
         let windows = self.get_all_windows()?;
         let mut eve_windows = Vec::new();
@@ -431,17 +687,10 @@ impl WindowManager for HyprlandManager {
         for window in windows {
             if let Some(title) = window.get("title").and_then(|t| t.as_str()) {
                 if title.starts_with("EVE - ") && !title.contains("Launcher") {
-                    // Hyprland uses hex addresses, we'll hash it to a u32
+                    // Hyprland identifies windows by hex address
                     if let Some(address) = window.get("address").and_then(|a| a.as_str()) {
-                        // Convert hex address like "0x12345678" to u32
-                        let id = if let Some(hex) = address.strip_prefix("0x") {
-                            u32::from_str_radix(hex, 16).unwrap_or(0)
-                        } else {
-                            0
-                        };
-
                         eve_windows.push(EveWindow {
-                            id,
+                            id: hyprland_ipc::address_to_handle(address),
                             title: title.trim_start_matches("EVE - ").to_string(),
                         });
                     }
@@ -452,25 +701,9 @@ impl WindowManager for HyprlandManager {
         Ok(eve_windows)
     }
 
-    fn activate_window(&self, window_id: u32) -> Result<()> {
-        // Convert u32 back to hex address
-        let address = format!("0x{:x}", window_id);
-
-        let output = Command::new("hyprctl")
-            .arg("dispatch")
-            .arg("focuswindow")
-            .arg(format!("address:{}", address))
-            .output()
-            .context("Failed to activate window")?;
-
-        if !output.status.success() {
-            anyhow::bail!(
-                "Failed to activate window: {}",
-                String::from_utf8_lossy(&output.stderr)
-            );
-        }
-
-        Ok(())
+    fn activate_window(&self, window_id: &WindowHandle) -> Result<()> {
+        hyprland_ipc::dispatch(&self.dir, &[format!("focuswindow address:{}", window_id)])
+            .context("Failed to activate window")
     }
 
     fn stack_windows(&self, windows: &[EveWindow], config: &Config) -> Result<()> {
@@ -479,69 +712,44 @@ impl WindowManager for HyprlandManager {
         let width = config.eve_width as i32;
         let height = (config.display_height - config.panel_height) as i32;
 
-        for window in windows {
-            let address = format!("0x{:x}", window.id);
-
-            // Enable floating
-            Command::new("hyprctl")
-                .arg("dispatch")
-                .arg("togglefloating")
-                .arg(format!("address:{}", address))
-                .output()?;
-
-            // Move window
-            Command::new("hyprctl")
-                .arg("dispatch")
-                .arg("movewindowpixel")
-                .arg(format!("exact {} {},address:{}", x, y, address))
-                .output()?;
-
-            // Resize window
-            Command::new("hyprctl")
-                .arg("dispatch")
-                .arg("resizewindowpixel")
-                .arg(format!("exact {} {},address:{}", width, height, address))
-                .output()?;
-        }
-
-        Ok(())
+        // Float, move and resize every window in a single batch
+        let dispatches: Vec<String> = windows
+            .iter()
+            .flat_map(|window| {
+                let address = &window.id;
+                [
+                    format!("togglefloating address:{}", address),
+                    format!("movewindowpixel exact {} {},address:{}", x, y, address),
+                    format!(
+                        "resizewindowpixel exact {} {},address:{}",
+                        width, height, address
+                    ),
+                ]
+            })
+            .collect();
+
+        hyprland_ipc::dispatch(&self.dir, &dispatches)
     }
 
-    fn get_active_window(&self) -> Result<u32> {
-        let output = Command::new("hyprctl")
-            .arg("activewindow")
-            .arg("-j")
-            .output()
+    fn get_active_window(&self) -> Result<WindowHandle> {
+        let window = hyprland_ipc::query(&self.dir, "activewindow")
             .context("Failed to get active window")?;
 
-        let window: Value =
-            serde_json::from_slice(&output.stdout).context("Failed to parse hyprctl output")?;
-
         if let Some(address) = window.get("address").and_then(|a| a.as_str()) {
-            let id = if let Some(hex) = address.strip_prefix("0x") {
-                u32::from_str_radix(hex, 16).unwrap_or(0)
-            } else {
-                0
-            };
-            return Ok(id);
+            return Ok(hyprland_ipc::address_to_handle(address));
         }
 
         anyhow::bail!("Failed to get active window ID")
     }
 
-    fn find_window_by_title(&self, title: &str) -> Result<Option<u32>> {
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowHandle>> {
         let windows = self.get_all_windows()?;
 
         for window in windows {
             if let Some(window_title) = window.get("title").and_then(|t| t.as_str()) {
                 if window_title == title {
                     if let Some(address) = window.get("address").and_then(|a| a.as_str()) {
-                        let id = if let Some(hex) = address.strip_prefix("0x") {
-                            u32::from_str_radix(hex, 16).unwrap_or(0)
-                        } else {
-                            0
-                        };
-                        return Ok(Some(id));
+                        return Ok(Some(hyprland_ipc::address_to_handle(address)));
                     }
                 }
             }
@@ -550,30 +758,158 @@ impl WindowManager for HyprlandManager {
         Ok(None)
     }
 
-    fn minimize_window(&self, window_id: u32) -> Result<()> {
-        let address = format!("0x{:x}", window_id);
-        Command::new("hyprctl")
-            .args([
-                "dispatch",
-                "movetoworkspacesilent",
-                &format!("special,address:{}", address),
-            ])
-            .output()
-            .context("Failed to minimize window")?;
-        Ok(())
+    fn minimize_window(&self, window_id: &WindowHandle) -> Result<()> {
+        hyprland_ipc::dispatch(
+            &self.dir,
+            &[format!(
+                "movetoworkspacesilent special,address:{}",
+                window_id
+            )],
+        )
+        .context("Failed to minimize window")
     }
 
-    fn restore_window(&self, window_id: u32) -> Result<()> {
-        let address = format!("0x{:x}", window_id);
+    fn restore_window(&self, window_id: &WindowHandle) -> Result<()> {
         // Move back to current workspace
-        Command::new("hyprctl")
-            .args([
-                "dispatch",
-                "movetoworkspace",
-                &format!("e+0,address:{}", address),
-            ])
-            .output()
-            .context("Failed to restore window")?;
-        Ok(())
+        hyprland_ipc::dispatch(
+            &self.dir,
+            &[format!("movetoworkspace e+0,address:{}", window_id)],
+        )
+        .context("Failed to restore window")
+    }
+
+    fn watch_windows(&self) -> Result<Option<Receiver<WindowEvent>>> {
+        let events = HyprlandEvents::connect(&self.dir)?;
+        let (tx, rx) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for event in events {
+                let Ok((event, data)) = event else {
+                    break;
+                };
+
+                let change = match event.as_str() {
+                    "activewindowv2" => Some(WindowEvent::ActiveChanged(
+                        hyprland_ipc::address_to_handle(&data),
+                    )),
+                    // ADDRESS,WORKSPACE,CLASS,TITLE
+                    "openwindow" => data
+                        .splitn(4, ',')
+                        .nth(3)
+                        .filter(|title| title.starts_with("EVE"))
+                        .map(|_| WindowEvent::WindowsChanged),
+                    "closewindow" | "windowtitle" => Some(WindowEvent::WindowsChanged),
+                    _ => None,
+                };
+
+                if let Some(change) = change {
+                    if tx.send(change).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Some(rx))
+    }
+}
+
+// ============================================================================
+// Generic wlroots Backend (via wlr-foreign-toplevel-management)
+// ============================================================================
+
+/// Any compositor implementing `zwlr_foreign_toplevel_manager_v1`. The
+/// protocol can activate and minimize windows but not place them.
+pub struct WlrManager {
+    toplevels: ForeignToplevels,
+}
+
+impl WlrManager {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            toplevels: ForeignToplevels::connect()?,
+        })
+    }
+}
+
+impl WindowManager for WlrManager {
+    fn name(&self) -> &'static str {
+        "wlr-foreign-toplevel"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            move_windows: false,
+            resize_windows: false,
+            minimize: true,
+            stacking: false,
+            events: true,
+            thumbnails: false,
+            overlay_dragging: false,
+        }
+    }
+
+    fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
+        let eve_windows = self
+            .toplevels
+            .list()
+            .into_iter()
+            .filter(|toplevel| {
+                toplevel.title.starts_with("EVE - ") && !toplevel.title.contains("Launcher")
+            })
+            .map(|toplevel| EveWindow {
+                id: WindowHandle::Id(toplevel.id),
+                title: toplevel.title.trim_start_matches("EVE - ").to_string(),
+            })
+            .collect();
+
+        Ok(eve_windows)
+    }
+
+    fn activate_window(&self, window_id: &WindowHandle) -> Result<()> {
+        self.toplevels
+            .activate(window_id)
+            .context("Failed to activate window")
+    }
+
+    fn stack_windows(&self, _windows: &[EveWindow], _config: &Config) -> Result<()> {
+        anyhow::bail!(
+            "Window stacking is not supported by wlr-foreign-toplevel-management; \
+             arrange EVE windows with your compositor instead"
+        )
+    }
+
+    fn get_active_window(&self) -> Result<WindowHandle> {
+        self.toplevels
+            .list()
+            .into_iter()
+            .find(|toplevel| toplevel.activated)
+            .map(|toplevel| WindowHandle::Id(toplevel.id))
+            .context("No active window found")
+    }
+
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowHandle>> {
+        Ok(self
+            .toplevels
+            .list()
+            .into_iter()
+            .find(|toplevel| toplevel.title == title)
+            .map(|toplevel| WindowHandle::Id(toplevel.id)))
+    }
+
+    fn minimize_window(&self, window_id: &WindowHandle) -> Result<()> {
+        self.toplevels
+            .set_minimized(window_id, true)
+            .context("Failed to minimize window")
+    }
+
+    fn restore_window(&self, window_id: &WindowHandle) -> Result<()> {
+        self.toplevels
+            .set_minimized(window_id, false)
+            .context("Failed to restore window")
+    }
+
+    fn watch_windows(&self) -> Result<Option<Receiver<WindowEvent>>> {
+        Ok(Some(self.toplevels.watch()))
     }
 }
@@ -1,41 +1,186 @@
 use crate::config::Config;
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::mpsc::Receiver;
+
+/// Backend-specific window identity, handed back to the backend unchanged.
+/// Numeric ids (X11 windows, Sway con_ids, Hyprland addresses) keep their full
+/// 64 bits; anything else, such as KWin window UUIDs, stays a string.
+///
+/// Serialized as a string (`0x...` for numeric ids) so it survives JSON
+/// consumers that can't represent 64-bit integers.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", from = "String")]
+pub enum WindowHandle {
+    Id(u64),
+    Name(String),
+}
+
+impl WindowHandle {
+    /// The numeric id, for backends that use them
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            WindowHandle::Id(id) => Some(*id),
+            WindowHandle::Name(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for WindowHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowHandle::Id(id) => write!(f, "0x{:x}", id),
+            WindowHandle::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+impl From<u32> for WindowHandle {
+    fn from(id: u32) -> Self {
+        WindowHandle::Id(u64::from(id))
+    }
+}
+
+impl From<u64> for WindowHandle {
+    fn from(id: u64) -> Self {
+        WindowHandle::Id(id)
+    }
+}
+
+impl From<String> for WindowHandle {
+    /// Inverse of `Display`: only the exact `0x...` form it produces is read
+    /// back as a numeric id, so every handle round-trips unchanged
+    fn from(handle: String) -> Self {
+        handle
+            .strip_prefix("0x")
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+            .filter(|id| format!("0x{:x}", id) == handle)
+            .map(WindowHandle::Id)
+            .unwrap_or(WindowHandle::Name(handle))
+    }
+}
+
+impl From<WindowHandle> for String {
+    fn from(handle: WindowHandle) -> Self {
+        handle.to_string()
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct EveWindow {
-    pub id: u32,
+    pub id: WindowHandle,
     pub title: String,
 }
 
+/// Window changes pushed by backends that can watch the display server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowEvent {
+    /// A client appeared, disappeared or changed its title
+    WindowsChanged,
+    /// Focus moved to this window (not necessarily an EVE client)
+    ActiveChanged(WindowHandle),
+}
+
+/// What a backend can actually do on the running compositor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// `move_window` places windows
+    pub move_windows: bool,
+    /// Windows can be resized (part of stacking)
+    pub resize_windows: bool,
+    /// `minimize_window` really minimizes, so `minimize_inactive` can use it
+    pub minimize: bool,
+    /// `stack_windows` works
+    pub stacking: bool,
+    /// `watch_windows` pushes changes instead of the daemon polling
+    pub events: bool,
+    /// Live client previews can be shown
+    pub thumbnails: bool,
+    /// The overlay can be dragged and its position saved
+    pub overlay_dragging: bool,
+}
+
 /// Trait for window management across different display servers and compositors
 pub trait WindowManager: Send + Sync {
+    /// Short backend name for messages, e.g. "Sway"
+    fn name(&self) -> &'static str;
+
+    /// What this backend supports
+    fn capabilities(&self) -> Capabilities;
+
+    /// Explain that `feature` isn't available with this backend
+    fn unsupported(&self, feature: &str) -> String {
+        format!(
+            "{} is not available with the {} backend",
+            feature,
+            self.name()
+        )
+    }
+
     /// Get all EVE Online client windows
     fn get_eve_windows(&self) -> Result<Vec<EveWindow>>;
 
     /// Activate/focus a specific window by ID
-    fn activate_window(&self, window_id: u32) -> Result<()>;
+    fn activate_window(&self, window_id: &WindowHandle) -> Result<()>;
 
     /// Stack all EVE windows at the same position (centered)
     fn stack_windows(&self, windows: &[EveWindow], config: &Config) -> Result<()>;
 
     /// Get the currently active window ID
-    fn get_active_window(&self) -> Result<u32>;
+    fn get_active_window(&self) -> Result<WindowHandle>;
 
     /// Find a window by its title (returns window ID if found)
-    fn find_window_by_title(&self, title: &str) -> Result<Option<u32>>;
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowHandle>>;
 
-    /// Move a window to a specific position (X11 only, no-op on Wayland)
-    fn move_window(&self, window_id: u32, x: i32, y: i32) -> Result<()> {
-        // Default implementation: no-op (Wayland doesn't allow arbitrary window positioning)
+    /// Move a window to a specific position, if `capabilities().move_windows`
+    fn move_window(&self, window_id: &WindowHandle, x: i32, y: i32) -> Result<()> {
+        // Most Wayland compositors don't allow arbitrary window positioning
         let _ = (window_id, x, y);
-        Ok(())
+        anyhow::bail!(self.unsupported("Moving windows"))
     }
 
     /// Minimize a window
-    fn minimize_window(&self, window_id: u32) -> Result<()>;
+    fn minimize_window(&self, window_id: &WindowHandle) -> Result<()>;
 
     /// Restore a minimized window
-    fn restore_window(&self, window_id: u32) -> Result<()>;
+    fn restore_window(&self, window_id: &WindowHandle) -> Result<()>;
+
+    /// Start watching for window changes in the background.
+    /// Returns None if the backend can't, in which case callers poll instead.
+    fn watch_windows(&self) -> Result<Option<Receiver<WindowEvent>>> {
+        Ok(None)
+    }
+}
+
+/// Which backend to use, from the `backend` config key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Pick one from the session environment
+    #[default]
+    Auto,
+    X11,
+    #[serde(alias = "kde")]
+    Kwin,
+    Sway,
+    Hyprland,
+    /// Any compositor with wlr-foreign-toplevel-management
+    #[serde(alias = "wlroots")]
+    Wlr,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Backend::Auto => "auto",
+            Backend::X11 => "x11",
+            Backend::Kwin => "kwin",
+            Backend::Sway => "sway",
+            Backend::Hyprland => "hyprland",
+            Backend::Wlr => "wlr",
+        })
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -72,31 +217,146 @@ pub fn detect_display_server() -> DisplayServer {
 
 /// Detect which Wayland compositor is running
 pub fn detect_wayland_compositor() -> WaylandCompositor {
-    // Check XDG_CURRENT_DESKTOP first
-    if let Ok(desktop) = std::env::var("XDG_CURRENT_DESKTOP") {
-        let desktop_lower = desktop.to_lowercase();
-        if desktop_lower.contains("kde") {
-            return WaylandCompositor::Kde;
-        }
-        if desktop_lower.contains("gnome") {
-            return WaylandCompositor::Gnome;
-        }
-        if desktop_lower.contains("sway") {
-            return WaylandCompositor::Sway;
-        }
-        if desktop_lower.contains("hyprland") {
-            return WaylandCompositor::Hyprland;
+    // A live IPC socket beats desktop names, which nested sessions inherit
+    if std::env::var_os("SWAYSOCK").is_some_and(|path| std::path::Path::new(&path).exists()) {
+        return WaylandCompositor::Sway;
+    }
+
+    if crate::hyprland_ipc::socket_dir().is_ok() {
+        return WaylandCompositor::Hyprland;
+    }
+
+    std::env::var("XDG_CURRENT_DESKTOP")
+        .ok()
+        .and_then(|desktop| compositor_from_desktop(&desktop))
+        .unwrap_or(WaylandCompositor::Other)
+}
+
+/// Read `XDG_CURRENT_DESKTOP`, a colon-separated list such as `KDE` or `sway:wlroots`
+fn compositor_from_desktop(desktop: &str) -> Option<WaylandCompositor> {
+    let names: Vec<String> = desktop
+        .split(':')
+        .map(|name| name.trim().to_lowercase())
+        .collect();
+    let has = |name: &str| names.iter().any(|n| n == name);
+
+    if has("sway") {
+        Some(WaylandCompositor::Sway)
+    } else if has("hyprland") {
+        Some(WaylandCompositor::Hyprland)
+    } else if has("wlroots") {
+        // wlroots compositors sometimes claim KDE or GNOME for theming; KWin never says wlroots
+        Some(WaylandCompositor::Other)
+    } else if has("kde") {
+        Some(WaylandCompositor::Kde)
+    } else if has("gnome") {
+        Some(WaylandCompositor::Gnome)
+    } else {
+        None
+    }
+}
+
+/// Pick a backend from the session environment, for `backend = "auto"`
+pub fn detect_backend() -> Result<Backend> {
+    Ok(match detect_display_server() {
+        DisplayServer::X11 => Backend::X11,
+        DisplayServer::Wayland => match detect_wayland_compositor() {
+            WaylandCompositor::Kde => Backend::Kwin,
+            WaylandCompositor::Sway => Backend::Sway,
+            WaylandCompositor::Hyprland => Backend::Hyprland,
+            WaylandCompositor::Gnome => anyhow::bail!(
+                "GNOME Shell is not yet supported due to restrictive window management APIs"
+            ),
+            WaylandCompositor::Other => Backend::Wlr,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(handle: WindowHandle) -> WindowHandle {
+        let json = serde_json::to_string(&handle).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_handles_round_trip() {
+        let handles = [
+            // X11 window id
+            WindowHandle::from(0x06e0_0008u32),
+            // Sway con_id
+            WindowHandle::from(94u64),
+            // Hyprland address wider than 32 bits
+            WindowHandle::from(0x55d1_c2a8_e0f0u64),
+            WindowHandle::Id(u64::MAX),
+            // KWin window UUID
+            WindowHandle::Name("{4c1a2b3d-0000-4e5f-8a9b-0c1d2e3f4a5b}".to_string()),
+        ];
+
+        for handle in handles {
+            assert_eq!(round_trip(handle.clone()), handle);
         }
     }
 
-    // Check for compositor-specific environment variables
-    if std::env::var("SWAYSOCK").is_ok() {
-        return WaylandCompositor::Sway;
+    #[test]
+    fn test_handle_serializes_as_string() {
+        assert_eq!(
+            serde_json::to_string(&WindowHandle::from(0x55d1_c2a8_e0f0u64)).unwrap(),
+            r#""0x55d1c2a8e0f0""#
+        );
     }
 
-    if std::env::var("HYPRLAND_INSTANCE_SIGNATURE").is_ok() {
-        return WaylandCompositor::Hyprland;
+    #[test]
+    fn test_compositor_from_desktop() {
+        assert_eq!(compositor_from_desktop("KDE"), Some(WaylandCompositor::Kde));
+        assert_eq!(
+            compositor_from_desktop("ubuntu:GNOME"),
+            Some(WaylandCompositor::Gnome)
+        );
+        assert_eq!(
+            compositor_from_desktop("sway:wlroots"),
+            Some(WaylandCompositor::Sway)
+        );
+        // A wlroots compositor posing as KDE can't be scripted through KWin
+        assert_eq!(
+            compositor_from_desktop("KDE:wlroots"),
+            Some(WaylandCompositor::Other)
+        );
+        // Only whole names count
+        assert_eq!(compositor_from_desktop("kdesktop"), None);
+        assert_eq!(compositor_from_desktop(""), None);
+    }
+
+    #[test]
+    fn test_backend_names() {
+        #[derive(Deserialize)]
+        struct Section {
+            backend: Backend,
+        }
+        let parse = |value: &str| {
+            toml::from_str::<Section>(&format!("backend = \"{}\"", value))
+                .map(|section| section.backend)
+        };
+
+        assert_eq!(parse("hyprland").unwrap(), Backend::Hyprland);
+        assert_eq!(parse("kde").unwrap(), Backend::Kwin);
+        assert_eq!(parse("wlroots").unwrap(), Backend::Wlr);
+        assert!(parse("gnome").is_err());
+
+        // Display gives back the config spelling
+        for backend in [Backend::Auto, Backend::X11, Backend::Kwin, Backend::Wlr] {
+            assert_eq!(parse(&backend.to_string()).unwrap(), backend);
+        }
     }
 
-    WaylandCompositor::Other
+    #[test]
+    fn test_non_canonical_hex_stays_a_name() {
+        // Zero-padded ids aren't what Display produces, so they aren't numeric
+        assert_eq!(
+            WindowHandle::from("0x06e00008".to_string()),
+            WindowHandle::Name("0x06e00008".to_string())
+        );
+    }
 }
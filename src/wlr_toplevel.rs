@@ -0,0 +1,358 @@
+use crate::window_manager::{WindowEvent, WindowHandle};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{wl_registry, wl_seat::WlSeat};
+use wayland_client::{event_created_child, Connection, Dispatch, Proxy, QueueHandle};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_handle_v1::{
+    self, ZwlrForeignToplevelHandleV1,
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_manager_v1::{
+    self, ZwlrForeignToplevelManagerV1,
+};
+
+/// Ids handed out to toplevels. Protocol object ids get reused, these don't.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A toplevel as last announced by the compositor
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Toplevel {
+    pub id: u64,
+    pub title: String,
+    pub app_id: String,
+    pub activated: bool,
+    pub minimized: bool,
+}
+
+struct Entry {
+    handle: ZwlrForeignToplevelHandleV1,
+    /// Changes since the last `done` event
+    pending: Toplevel,
+    /// None until the first `done`
+    current: Option<Toplevel>,
+}
+
+type Entries = Arc<Mutex<HashMap<u64, Entry>>>;
+type EventSender = Arc<Mutex<Option<Sender<WindowEvent>>>>;
+
+/// Dispatch state, owned by the event thread
+struct State {
+    entries: Entries,
+    events: EventSender,
+}
+
+impl State {
+    fn publish(&self, changes: Vec<WindowEvent>) {
+        let mut events = self.events.lock().unwrap();
+        if let Some(sender) = events.as_ref() {
+            for change in changes {
+                if sender.send(change).is_err() {
+                    *events = None; // Nobody is listening anymore
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Toplevels of any compositor implementing `zwlr_foreign_toplevel_manager_v1`
+/// (river, labwc, Wayfire, niri, ...), kept current by a background thread
+pub struct ForeignToplevels {
+    conn: Connection,
+    seat: WlSeat,
+    entries: Entries,
+    events: EventSender,
+}
+
+impl ForeignToplevels {
+    pub fn connect() -> Result<Self> {
+        let conn = Connection::connect_to_env().context("Failed to connect to Wayland display")?;
+        let (globals, mut queue) = registry_queue_init::<State>(&conn)?;
+        let qh = queue.handle();
+
+        let _manager: ZwlrForeignToplevelManagerV1 = globals
+            .bind(&qh, 1..=3, ())
+            .context("Compositor doesn't support wlr-foreign-toplevel-management")?;
+        let seat: WlSeat = globals
+            .bind(&qh, 1..=1, ())
+            .context("Compositor has no seat")?;
+
+        let entries = Entries::default();
+        let events = EventSender::default();
+        let mut state = State {
+            entries: Arc::clone(&entries),
+            events: Arc::clone(&events),
+        };
+
+        // One round trip for the toplevels, one for their title/state/done
+        queue.roundtrip(&mut state)?;
+        queue.roundtrip(&mut state)?;
+
+        std::thread::spawn(move || {
+            while queue.blocking_dispatch(&mut state).is_ok() {}
+            eprintln!("Lost connection to the Wayland compositor");
+        });
+
+        Ok(Self {
+            conn,
+            seat,
+            entries,
+            events,
+        })
+    }
+
+    pub fn list(&self) -> Vec<Toplevel> {
+        let mut toplevels: Vec<Toplevel> = self
+            .entries
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|entry| entry.current.clone())
+            .collect();
+        // Oldest first, like other backends' stacking order
+        toplevels.sort_by_key(|toplevel| toplevel.id);
+        toplevels
+    }
+
+    pub fn activate(&self, handle: &WindowHandle) -> Result<()> {
+        self.request(handle, |toplevel| toplevel.activate(&self.seat))
+    }
+
+    pub fn set_minimized(&self, handle: &WindowHandle, minimized: bool) -> Result<()> {
+        self.request(handle, |toplevel| {
+            if minimized {
+                toplevel.set_minimized();
+            } else {
+                toplevel.unset_minimized();
+            }
+        })
+    }
+
+    /// Start forwarding focus and EVE window changes
+    pub fn watch(&self) -> Receiver<WindowEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.events.lock().unwrap().replace(tx);
+        rx
+    }
+
+    fn request(
+        &self,
+        handle: &WindowHandle,
+        request: impl FnOnce(&ZwlrForeignToplevelHandleV1),
+    ) -> Result<()> {
+        {
+            let entries = self.entries.lock().unwrap();
+            let entry = handle
+                .as_u64()
+                .and_then(|id| entries.get(&id))
+                .with_context(|| format!("Window {} not found", handle))?;
+            request(&entry.handle);
+        }
+
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+/// What a `done` event means for the daemon
+fn changes(before: Option<&Toplevel>, after: &Toplevel) -> Vec<WindowEvent> {
+    let is_eve = |toplevel: &Toplevel| toplevel.title.starts_with("EVE");
+    let mut changes = Vec::new();
+
+    // New clients and logins rename "EVE" to "EVE - <character>"
+    let retitled = before.map(|b| &b.title) != Some(&after.title);
+    if retitled && (is_eve(after) || before.is_some_and(is_eve)) {
+        changes.push(WindowEvent::WindowsChanged);
+    }
+
+    if after.activated && !before.is_some_and(|b| b.activated) {
+        changes.push(WindowEvent::ActiveChanged(WindowHandle::Id(after.id)));
+    }
+
+    changes
+}
+
+/// Read the `state` array (native-endian u32s) into (activated, minimized)
+fn parse_states(states: &[u8]) -> (bool, bool) {
+    let states: Vec<u32> = states
+        .chunks_exact(4)
+        .map(|chunk| u32::from_ne_bytes(chunk.try_into().unwrap()))
+        .collect();
+    let has = |state: zwlr_foreign_toplevel_handle_v1::State| states.contains(&(state as u32));
+
+    (
+        has(zwlr_foreign_toplevel_handle_v1::State::Activated),
+        has(zwlr_foreign_toplevel_handle_v1::State::Minimized),
+    )
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlSeat, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &WlSeat,
+        _: <WlSeat as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+            let id = *toplevel.data::<u64>().unwrap();
+            state.entries.lock().unwrap().insert(
+                id,
+                Entry {
+                    handle: toplevel,
+                    pending: Toplevel {
+                        id,
+                        ..Toplevel::default()
+                    },
+                    current: None,
+                },
+            );
+        }
+    }
+
+    event_created_child!(State, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (
+            ZwlrForeignToplevelHandleV1,
+            NEXT_ID.fetch_add(1, Ordering::SeqCst)
+        ),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, u64> for State {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        id: &u64,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use zwlr_foreign_toplevel_handle_v1::Event;
+
+        let mut entries = state.entries.lock().unwrap();
+        let Some(entry) = entries.get_mut(id) else {
+            return;
+        };
+
+        let changed = match event {
+            Event::Title { title } => {
+                entry.pending.title = title;
+                Vec::new()
+            }
+            Event::AppId { app_id } => {
+                entry.pending.app_id = app_id;
+                Vec::new()
+            }
+            Event::State { state } => {
+                (entry.pending.activated, entry.pending.minimized) = parse_states(&state);
+                Vec::new()
+            }
+            Event::Done => {
+                let changed = changes(entry.current.as_ref(), &entry.pending);
+                entry.current = Some(entry.pending.clone());
+                changed
+            }
+            Event::Closed => {
+                let was_eve = entry
+                    .current
+                    .as_ref()
+                    .is_some_and(|toplevel| toplevel.title.starts_with("EVE"));
+                entries.remove(id);
+                handle.destroy();
+                if was_eve {
+                    vec![WindowEvent::WindowsChanged]
+                } else {
+                    Vec::new()
+                }
+            }
+            _ => Vec::new(),
+        };
+
+        drop(entries);
+        state.publish(changed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toplevel(id: u64, title: &str, activated: bool) -> Toplevel {
+        Toplevel {
+            id,
+            title: title.to_string(),
+            app_id: "steam_app_8500".to_string(),
+            activated,
+            minimized: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_states() {
+        let states: Vec<u8> = [2u32, 1]
+            .iter()
+            .flat_map(|state| state.to_ne_bytes())
+            .collect();
+        assert_eq!(parse_states(&states), (true, true));
+        assert_eq!(parse_states(&0u32.to_ne_bytes()), (false, false));
+        assert_eq!(parse_states(&[]), (false, false));
+    }
+
+    #[test]
+    fn test_new_eve_client_and_login() {
+        let launched = toplevel(4, "EVE", false);
+        assert_eq!(changes(None, &launched), vec![WindowEvent::WindowsChanged]);
+
+        let logged_in = toplevel(4, "EVE - Alpha", true);
+        assert_eq!(
+            changes(Some(&launched), &logged_in),
+            vec![
+                WindowEvent::WindowsChanged,
+                WindowEvent::ActiveChanged(WindowHandle::Id(4))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_other_windows_only_report_focus() {
+        let browser = toplevel(7, "Browser", false);
+        assert!(changes(None, &browser).is_empty());
+
+        let renamed = toplevel(7, "Browser - new tab", true);
+        assert_eq!(
+            changes(Some(&browser), &renamed),
+            vec![WindowEvent::ActiveChanged(WindowHandle::Id(7))]
+        );
+
+        // Still focused, nothing new
+        assert!(changes(Some(&renamed), &renamed).is_empty());
+    }
+}
@@ -1,15 +1,91 @@
 use crate::config::Config;
-use crate::window_manager::{EveWindow, WindowManager};
+use crate::window_manager::{Capabilities, EveWindow, WindowEvent, WindowHandle, WindowManager};
 use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Arc;
 use x11rb::connection::Connection;
 use x11rb::protocol::xproto::*;
+use x11rb::protocol::Event;
 use x11rb::rust_connection::RustConnection;
 
+x11rb::atom_manager! {
+    /// Every EWMH/ICCCM atom the manager uses, interned once at startup.
+    /// Atoms are server-wide, so the watcher connection shares the table.
+    pub Atoms: AtomsCookie {
+        _NET_CLIENT_LIST,
+        _NET_ACTIVE_WINDOW,
+        _NET_WM_NAME,
+        UTF8_STRING,
+        WM_CHANGE_STATE,
+    }
+}
+
+/// The X11 window behind a handle
+pub fn x11_window(handle: &WindowHandle) -> Result<Window> {
+    handle
+        .as_u64()
+        .and_then(|id| Window::try_from(id).ok())
+        .ok_or_else(|| anyhow::anyhow!("Not an X11 window: {}", handle))
+}
+
+/// Fetch the titles of many windows at once. Every request is sent before
+/// any reply is awaited, so a pass costs one roundtrip instead of one per window.
+fn window_titles(conn: &impl Connection, atoms: &Atoms, windows: &[Window]) -> Result<Vec<String>> {
+    // Try _NET_WM_NAME first (UTF-8)
+    let cookies = windows
+        .iter()
+        .map(|&window| {
+            conn.get_property(
+                false,
+                window,
+                atoms._NET_WM_NAME,
+                atoms.UTF8_STRING,
+                0,
+                1024,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut titles: Vec<Option<String>> = cookies
+        .into_iter()
+        .map(|cookie| {
+            cookie
+                .reply()
+                .ok()
+                .filter(|reply| !reply.value.is_empty())
+                .and_then(|reply| String::from_utf8(reply.value).ok())
+        })
+        .collect();
+
+    // Fall back to WM_NAME for the windows that had none, again pipelined
+    let fallback_cookies = windows
+        .iter()
+        .zip(&titles)
+        .filter(|(_, title)| title.is_none())
+        .map(|(&window, _)| {
+            conn.get_property(false, window, AtomEnum::WM_NAME, AtomEnum::STRING, 0, 1024)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut fallbacks = fallback_cookies.into_iter().map(|cookie| {
+        cookie
+            .reply()
+            .ok()
+            .map(|reply| String::from_utf8_lossy(&reply.value).to_string())
+    });
+
+    for title in titles.iter_mut().filter(|title| title.is_none()) {
+        *title = fallbacks.next().flatten();
+    }
+
+    Ok(titles.into_iter().map(Option::unwrap_or_default).collect())
+}
+
 pub struct X11Manager {
     conn: Arc<RustConnection>,
     screen_num: usize,
-    net_active_window_atom: Atom,
+    atoms: Atoms,
 }
 
 impl X11Manager {
@@ -19,54 +95,54 @@ impl X11Manager {
 
         let conn = Arc::new(conn);
 
-        // Pre-cache the _NET_ACTIVE_WINDOW atom (do roundtrip once at startup)
-        let net_active_window_atom = conn
-            .intern_atom(false, b"_NET_ACTIVE_WINDOW")?
-            .reply()?
-            .atom;
+        // Intern all atoms up front (requests are pipelined, one roundtrip)
+        let atoms = Atoms::new(&*conn)?.reply()?;
 
         Ok(Self {
             conn,
             screen_num,
-            net_active_window_atom,
+            atoms,
         })
     }
 
-    pub fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
+    fn client_list(&self) -> Result<Vec<Window>> {
         let screen = &self.conn.setup().roots[self.screen_num];
         let root = screen.root;
 
-        // Get _NET_CLIENT_LIST atom
-        let net_client_list = self
-            .conn
-            .intern_atom(false, b"_NET_CLIENT_LIST")?
-            .reply()?
-            .atom;
-
-        // Get list of all windows
         let client_list_reply = self
             .conn
-            .get_property(false, root, net_client_list, AtomEnum::WINDOW, 0, u32::MAX)?
+            .get_property(
+                false,
+                root,
+                self.atoms._NET_CLIENT_LIST,
+                AtomEnum::WINDOW,
+                0,
+                u32::MAX,
+            )?
             .reply()?;
 
-        let windows: Vec<u32> = client_list_reply
+        let windows = client_list_reply
             .value32()
             .ok_or_else(|| anyhow::anyhow!("Failed to get window list"))?
             .collect();
 
-        let mut eve_windows = Vec::new();
+        Ok(windows)
+    }
 
-        for &window in &windows {
-            if let Ok(title) = self.get_window_title(window) {
-                // Filter for EVE windows (steam_app_8500) and exclude launcher
-                if title.starts_with("EVE - ") && !title.contains("Launcher") {
-                    eve_windows.push(EveWindow {
-                        id: window,
-                        title: title.trim_start_matches("EVE - ").to_string(),
-                    });
-                }
-            }
-        }
+    pub fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
+        let windows = self.client_list()?;
+        let titles = window_titles(&*self.conn, &self.atoms, &windows)?;
+
+        let eve_windows = windows
+            .into_iter()
+            .zip(titles)
+            // Filter for EVE windows (steam_app_8500) and exclude launcher
+            .filter(|(_, title)| title.starts_with("EVE - ") && !title.contains("Launcher"))
+            .map(|(window, title)| EveWindow {
+                id: window.into(),
+                title: title.trim_start_matches("EVE - ").to_string(),
+            })
+            .collect();
 
         Ok(eve_windows)
     }
@@ -75,15 +151,16 @@ impl X11Manager {
         let screen = &self.conn.setup().roots[self.screen_num];
         let root = screen.root;
 
-        let net_active_window = self
-            .conn
-            .intern_atom(false, b"_NET_ACTIVE_WINDOW")?
-            .reply()?
-            .atom;
-
         let reply = self
             .conn
-            .get_property(false, root, net_active_window, AtomEnum::WINDOW, 0, 1)?
+            .get_property(
+                false,
+                root,
+                self.atoms._NET_ACTIVE_WINDOW,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )?
             .reply()?;
 
         let active: Vec<u32> = reply
@@ -105,7 +182,7 @@ impl X11Manager {
             format: 32,
             sequence: 0,
             window: window_id,
-            type_: self.net_active_window_atom,
+            type_: self.atoms._NET_ACTIVE_WINDOW,
             data: ClientMessageData::from([2, x11rb::CURRENT_TIME, current_active, 0, 0]),
         };
 
@@ -139,74 +216,23 @@ impl X11Manager {
                 .width(width)
                 .height(height);
 
-            self.conn.configure_window(window.id, &values)?;
+            self.conn
+                .configure_window(x11_window(&window.id)?, &values)?;
         }
 
         self.conn.flush()?;
         Ok(())
     }
 
-    fn get_window_title(&self, window: u32) -> Result<String> {
-        // Try _NET_WM_NAME first (UTF-8)
-        let net_wm_name = self.conn.intern_atom(false, b"_NET_WM_NAME")?.reply()?.atom;
-
-        let utf8_string = self.conn.intern_atom(false, b"UTF8_STRING")?.reply()?.atom;
-
-        if let Ok(reply) = self
-            .conn
-            .get_property(false, window, net_wm_name, utf8_string, 0, 1024)?
-            .reply()
-        {
-            if !reply.value.is_empty() {
-                if let Ok(title) = String::from_utf8(reply.value.clone()) {
-                    return Ok(title);
-                }
-            }
-        }
-
-        // Fall back to WM_NAME
-        if let Ok(reply) = self
-            .conn
-            .get_property(false, window, AtomEnum::WM_NAME, AtomEnum::STRING, 0, 1024)?
-            .reply()
-        {
-            if !reply.value.is_empty() {
-                return Ok(String::from_utf8_lossy(&reply.value).to_string());
-            }
-        }
-
-        Ok(String::new())
-    }
-
     pub fn find_window_by_title(&self, title: &str) -> Result<Option<u32>> {
-        let screen = &self.conn.setup().roots[self.screen_num];
-        let root = screen.root;
-
-        let net_client_list = self
-            .conn
-            .intern_atom(false, b"_NET_CLIENT_LIST")?
-            .reply()?
-            .atom;
-
-        let client_list_reply = self
-            .conn
-            .get_property(false, root, net_client_list, AtomEnum::WINDOW, 0, u32::MAX)?
-            .reply()?;
-
-        let windows: Vec<u32> = client_list_reply
-            .value32()
-            .ok_or_else(|| anyhow::anyhow!("Failed to get window list"))?
-            .collect();
-
-        for &window in &windows {
-            if let Ok(window_title) = self.get_window_title(window) {
-                if window_title == title {
-                    return Ok(Some(window));
-                }
-            }
-        }
-
-        Ok(None)
+        let windows = self.client_list()?;
+        let titles = window_titles(&*self.conn, &self.atoms, &windows)?;
+
+        Ok(windows
+            .into_iter()
+            .zip(titles)
+            .find(|(_, window_title)| window_title == title)
+            .map(|(window, _)| window))
     }
 
     pub fn move_window(&self, window_id: u32, x: i32, y: i32) -> Result<()> {
@@ -218,12 +244,6 @@ impl X11Manager {
 
     pub fn minimize_window(&self, window_id: u32) -> Result<()> {
         // Use WM_CHANGE_STATE with IconicState to minimize
-        let wm_change_state = self
-            .conn
-            .intern_atom(false, b"WM_CHANGE_STATE")?
-            .reply()?
-            .atom;
-
         let screen = &self.conn.setup().roots[self.screen_num];
         let root = screen.root;
 
@@ -233,7 +253,7 @@ impl X11Manager {
             format: 32,
             sequence: 0,
             window: window_id,
-            type_: wm_change_state,
+            type_: self.atoms.WM_CHANGE_STATE,
             data: ClientMessageData::from([3u32, 0, 0, 0, 0]),
         };
 
@@ -256,13 +276,166 @@ impl X11Manager {
     }
 }
 
+/// Watches root and client window properties on a dedicated connection and
+/// turns PropertyNotify events into `WindowEvent`s
+struct X11Watcher {
+    conn: RustConnection,
+    root: Window,
+    atoms: Atoms,
+    clients: HashSet<Window>,
+    eve_clients: HashSet<Window>,
+}
+
+impl X11Watcher {
+    fn connect(atoms: Atoms) -> Result<Self> {
+        let (conn, screen_num) =
+            RustConnection::connect(None).context("Failed to connect to X11 server")?;
+        let root = conn.setup().roots[screen_num].root;
+
+        // The window manager updates the client list and active window on the root
+        conn.change_window_attributes(
+            root,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )?;
+
+        let mut watcher = Self {
+            conn,
+            root,
+            atoms,
+            clients: HashSet::new(),
+            eve_clients: HashSet::new(),
+        };
+        watcher.watch_clients()?;
+        watcher.conn.flush()?;
+
+        Ok(watcher)
+    }
+
+    /// Select title changes on any client we aren't watching yet
+    fn watch_clients(&mut self) -> Result<()> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                self.root,
+                self.atoms._NET_CLIENT_LIST,
+                AtomEnum::WINDOW,
+                0,
+                u32::MAX,
+            )?
+            .reply()?;
+
+        let clients: HashSet<Window> = reply
+            .value32()
+            .ok_or_else(|| anyhow::anyhow!("Failed to get window list"))?
+            .collect();
+
+        let new: Vec<Window> = clients.difference(&self.clients).copied().collect();
+        for &window in &new {
+            // The window may already be gone, that's fine
+            let _ = self.conn.change_window_attributes(
+                window,
+                &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+            );
+        }
+        for (window, title) in new
+            .iter()
+            .zip(window_titles(&self.conn, &self.atoms, &new)?)
+        {
+            if title.starts_with("EVE") {
+                self.eve_clients.insert(*window);
+            }
+        }
+
+        self.eve_clients.retain(|window| clients.contains(window));
+        self.clients = clients;
+        Ok(())
+    }
+
+    fn title(&self, window: Window) -> Result<String> {
+        Ok(window_titles(&self.conn, &self.atoms, &[window])?.remove(0))
+    }
+
+    fn active_window(&self) -> Result<Window> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                self.root,
+                self.atoms._NET_ACTIVE_WINDOW,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )?
+            .reply()?;
+
+        Ok(reply.value32().and_then(|mut v| v.next()).unwrap_or(0))
+    }
+
+    fn run(mut self, changes: Sender<WindowEvent>) -> Result<()> {
+        loop {
+            let Event::PropertyNotify(event) = self.conn.wait_for_event()? else {
+                continue;
+            };
+
+            let change = if event.window == self.root {
+                if event.atom == self.atoms._NET_CLIENT_LIST {
+                    self.watch_clients()?;
+                    self.conn.flush()?;
+                    Some(WindowEvent::WindowsChanged)
+                } else if event.atom == self.atoms._NET_ACTIVE_WINDOW {
+                    Some(WindowEvent::ActiveChanged(self.active_window()?.into()))
+                } else {
+                    None
+                }
+            } else if event.atom == self.atoms._NET_WM_NAME
+                || event.atom == u32::from(AtomEnum::WM_NAME)
+            {
+                // Only EVE windows matter, e.g. a client logging in or out
+                let was_eve = self.eve_clients.contains(&event.window);
+                let is_eve = self.title(event.window)?.starts_with("EVE");
+                if is_eve {
+                    self.eve_clients.insert(event.window);
+                } else {
+                    self.eve_clients.remove(&event.window);
+                }
+                (is_eve || was_eve).then_some(WindowEvent::WindowsChanged)
+            } else {
+                None
+            };
+
+            if let Some(change) = change {
+                if changes.send(change).is_err() {
+                    return Ok(()); // Nobody is listening anymore
+                }
+            }
+        }
+    }
+}
+
 impl WindowManager for X11Manager {
+    fn name(&self) -> &'static str {
+        "X11"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            move_windows: true,
+            resize_windows: true,
+            minimize: true,
+            stacking: true,
+            events: true,
+            thumbnails: true,
+            overlay_dragging: true,
+        }
+    }
+
     fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
         self.get_eve_windows()
     }
 
-    fn activate_window(&self, window_id: u32) -> Result<()> {
-        self.activate_window(window_id)
+    fn activate_window(&self, window_id: &WindowHandle) -> Result<()> {
+        self.activate_window(x11_window(window_id)?)
     }
 
     fn stack_windows(&self, windows: &[EveWindow], config: &Config) -> Result<()> {
@@ -274,23 +447,36 @@ impl WindowManager for X11Manager {
         self.stack_windows_internal(windows, x, y, width, height)
     }
 
-    fn get_active_window(&self) -> Result<u32> {
-        self.get_active_window()
+    fn get_active_window(&self) -> Result<WindowHandle> {
+        self.get_active_window().map(WindowHandle::from)
+    }
+
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowHandle>> {
+        Ok(self.find_window_by_title(title)?.map(WindowHandle::from))
     }
 
-    fn find_window_by_title(&self, title: &str) -> Result<Option<u32>> {
-        self.find_window_by_title(title)
+    fn move_window(&self, window_id: &WindowHandle, x: i32, y: i32) -> Result<()> {
+        self.move_window(x11_window(window_id)?, x, y)
     }
 
-    fn move_window(&self, window_id: u32, x: i32, y: i32) -> Result<()> {
-        self.move_window(window_id, x, y)
+    fn minimize_window(&self, window_id: &WindowHandle) -> Result<()> {
+        self.minimize_window(x11_window(window_id)?)
     }
 
-    fn minimize_window(&self, window_id: u32) -> Result<()> {
-        self.minimize_window(window_id)
+    fn restore_window(&self, window_id: &WindowHandle) -> Result<()> {
+        self.restore_window(x11_window(window_id)?)
     }
 
-    fn restore_window(&self, window_id: u32) -> Result<()> {
-        self.restore_window(window_id)
+    fn watch_windows(&self) -> Result<Option<Receiver<WindowEvent>>> {
+        let watcher = X11Watcher::connect(self.atoms)?;
+        let (tx, rx) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            if let Err(e) = watcher.run(tx) {
+                eprintln!("X11 window watcher stopped: {}", e);
+            }
+        });
+
+        Ok(Some(rx))
     }
 }